@@ -0,0 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// runtime api of the dpos pallet, lets wallets read the staking figures without recomputing them off-chain
+use codec::Codec;
+use sp_runtime::Perbill;
+
+sp_api::decl_runtime_apis! {
+	/// staking information computed by `pallet_dpos`
+	pub trait DposApi<AccountId> where AccountId: Codec {
+		/// yearly return of the staked funds before commission at the current staking ratio, saturates at 100%
+		fn current_apr() -> Perbill;
+
+		/// yearly inflation at the current staking ratio
+		fn current_inflation() -> Perbill;
+
+		/// total `ValidatorStakes` divided by the total issuance
+		fn staking_ratio() -> Perbill;
+
+		/// reputation score of a validator, zero if it never was in the validator set
+		fn reputation(validator: AccountId) -> Perbill;
+	}
+}
@@ -0,0 +1,33 @@
+// exact pro-rata split of a reward pool between the stakes backing a validator. the shares are computed in 128 bits
+// so `pool * stake` never overflows, and every share is rounded down so they never add up to more than the pool
+use sp_runtime::{
+	helpers_128bit::multiply_by_rational_with_rounding,
+	traits::{AtLeast32BitUnsigned, Zero},
+	Rounding,
+};
+use sp_std::prelude::*;
+
+/// `pool * stake / total`, rounded down. zero when `total` is zero
+pub fn share<Balance: AtLeast32BitUnsigned + Copy>(pool: Balance, stake: Balance, total: Balance) -> Balance {
+	if total.is_zero() {
+		return Zero::zero();
+	}
+	let share = multiply_by_rational_with_rounding(
+		pool.unique_saturated_into(),
+		stake.unique_saturated_into(),
+		total.unique_saturated_into(),
+		Rounding::Down,
+	)
+	.unwrap_or(u128::MAX);
+	Balance::unique_saturated_from(share).min(pool)
+}
+
+/// splits `pool` between `stakes` pro-rata, returns the shares in the order of `stakes` and the rounding remainder.
+/// the shares plus the remainder add up to `pool` exactly, the remainder is less than one unit per stake
+/// (all of `pool` when there is no stake)
+pub fn apportion<Balance: AtLeast32BitUnsigned + Copy>(pool: Balance, stakes: &[Balance]) -> (Vec<Balance>, Balance) {
+	let total = stakes.iter().fold(Balance::zero(), |total, stake| total.saturating_add(*stake));
+	let shares: Vec<Balance> = stakes.iter().map(|stake| share(pool, *stake, total)).collect();
+	let paid = shares.iter().fold(Balance::zero(), |paid, share| paid.saturating_add(*share));
+	(shares, pool.saturating_sub(paid))
+}
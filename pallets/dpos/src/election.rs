@@ -0,0 +1,223 @@
+// election strategies used to pick the validator set at the end of each epoch, and the glue to plug them
+// into the `frame_election_provider_support` machinery
+use crate::pallet::{BalanceOf, Config, Delegators, Pallet, PotentialValidators};
+use frame_election_provider_support::{
+	data_provider, BoundedSupportsOf, DataProviderBounds, ElectionDataProvider, ElectionProvider,
+	ElectionProviderBase, Support, VoterOf,
+};
+use codec::Encode;
+use frame_support::{
+	traits::Get,
+	BoundedVec,
+};
+use frame_system::pallet_prelude::BlockNumberFor;
+use sp_npos_elections::{seq_phragmen, ExtendedBalance, IdentifierT, VoteWeight};
+use sp_runtime::{
+	traits::{AtLeast32BitUnsigned, UniqueSaturatedInto, Zero},
+	Perbill,
+};
+use sp_std::{collections::btree_map::BTreeMap, marker::PhantomData, prelude::*};
+
+/// trait to pick the new validator set from the registered validators and the delegations backing them
+pub trait ElectionStrategy<AccountId, Balance> {
+	/// `candidates` are the registered validators with their self-stake, `delegations` are (delegator, validator, amount)
+	/// returns at most `max_winners` validators
+	fn elect(
+		candidates: Vec<(AccountId, Balance)>,
+		delegations: Vec<(AccountId, AccountId, Balance)>,
+		max_winners: usize,
+	) -> Vec<AccountId>;
+}
+
+/// top-N by total stake, self-stake plus everything delegated to the validator
+pub struct TopStake;
+
+impl<AccountId: Ord + Clone, Balance: AtLeast32BitUnsigned + Copy> ElectionStrategy<AccountId, Balance> for TopStake {
+	fn elect(
+		candidates: Vec<(AccountId, Balance)>,
+		delegations: Vec<(AccountId, AccountId, Balance)>,
+		max_winners: usize,
+	) -> Vec<AccountId> {
+		// sum the delegations on top of the self-stake, delegations to unknown validators are ignored
+		let mut stakes: BTreeMap<AccountId, Balance> = candidates.iter().cloned().collect();
+		for (_, validator, amount) in delegations {
+			if let Some(stake) = stakes.get_mut(&validator) {
+				*stake = stake.saturating_add(amount);
+			}
+		}
+
+		// keep the candidates order for equal stakes, the sort is stable
+		let mut totals: Vec<(AccountId, Balance)> = candidates
+			.into_iter()
+			.map(|(who, _)| {
+				let stake = stakes.get(&who).copied().unwrap_or_else(Zero::zero);
+				(who, stake)
+			})
+			.collect();
+		totals.sort_by_key(|(_, stake)| core::cmp::Reverse(*stake)); // sort by stake amount in descending order
+
+		totals.into_iter().take(max_winners).map(|(who, _)| who).collect()
+	}
+}
+
+/// sequential Phragmén over the `ballots`, one voter per account for all the validators it backs, and the validator
+/// with the lowest load is elected each round. a seat raises the load of every voter behind it, so a whale's next
+/// validator competes with the whale's whole stake already carrying a seat, while `TopStake` only compares the stake
+/// on each validator and hands a whale every validator it backs more than accounts spreading their stake do
+pub struct SequentialPhragmen;
+
+impl<AccountId: IdentifierT, Balance: AtLeast32BitUnsigned + Copy> ElectionStrategy<AccountId, Balance>
+	for SequentialPhragmen
+{
+	fn elect(
+		candidates: Vec<(AccountId, Balance)>,
+		delegations: Vec<(AccountId, AccountId, Balance)>,
+		max_winners: usize,
+	) -> Vec<AccountId> {
+		let candidate_ids: Vec<AccountId> = candidates.iter().map(|(who, _)| who.clone()).collect();
+		let voters = ballots(&candidates, &delegations);
+
+		// only the winners are used, so the stake distribution is not balanced afterwards
+		match seq_phragmen::<AccountId, Perbill>(max_winners, candidate_ids, voters, None) {
+			Ok(result) => result.winners.into_iter().map(|(who, _)| who).collect(),
+			Err(e) => {
+				log::error!("Phragmén election failed: {:?}, falling back to top stake", e);
+				TopStake::elect(candidates, delegations, max_winners)
+			},
+		}
+	}
+}
+
+/// function to turn self-stakes and delegations into NPoS ballots, one per account. an account votes with its
+/// self-stake and all its delegations for itself and every validator it delegates to, so it is a single voter however
+/// many validators it backs. delegations to unknown validators are ignored
+pub fn ballots<AccountId: Ord + Clone, Balance: AtLeast32BitUnsigned + Copy>(
+	candidates: &[(AccountId, Balance)],
+	delegations: &[(AccountId, AccountId, Balance)],
+) -> Vec<(AccountId, VoteWeight, Vec<AccountId>)> {
+	let self_stakes = candidates.iter().map(|(who, self_stake)| (who, who, *self_stake));
+	let backing = delegations
+		.iter()
+		.filter(|(_, validator, _)| candidates.iter().any(|(who, _)| who == validator))
+		.map(|(delegator, validator, amount)| (delegator, validator, *amount));
+
+	let mut ballots: BTreeMap<AccountId, (Balance, Vec<AccountId>)> = BTreeMap::new();
+	for (voter, target, amount) in self_stakes.chain(backing) {
+		let (weight, targets) = ballots.entry(voter.clone()).or_insert_with(|| (Zero::zero(), Vec::new()));
+		*weight = weight.saturating_add(amount);
+		if !targets.contains(target) {
+			targets.push(target.clone());
+		}
+	}
+	ballots.into_iter().map(|(who, (weight, targets))| (who, weight.unique_saturated_into(), targets)).collect()
+}
+
+/// an account votes for at most every validator it delegates to and itself
+pub struct MaxVotesPerVoter<T>(PhantomData<T>);
+
+impl<T: Config> Get<u32> for MaxVotesPerVoter<T> {
+	fn get() -> u32 {
+		T::MaxDelegationsPerDelegator::get().saturating_add(1)
+	}
+}
+
+/// `ElectionProvider` running one of the strategies above on the pallet's own storage, for runtimes that
+/// do not want `onchain::OnChainExecution` or a multi-phase provider
+pub struct StrategyElection<T, S>(PhantomData<(T, S)>);
+
+impl<T: Config, S> ElectionProviderBase for StrategyElection<T, S> {
+	type AccountId = T::AccountId;
+	type BlockNumber = BlockNumberFor<T>;
+	type Error = &'static str;
+	type MaxWinners = T::MaxValidators;
+	type DataProvider = Pallet<T>;
+}
+
+impl<T: Config, S: ElectionStrategy<T::AccountId, BalanceOf<T>>> ElectionProvider for StrategyElection<T, S> {
+	fn ongoing() -> bool {
+		false
+	}
+
+	fn elect() -> Result<BoundedSupportsOf<Self>, Self::Error> {
+		// the most reputable candidates come first, so the strategies hand them the ties
+		let candidates: Vec<(T::AccountId, BalanceOf<T>)> = Pallet::<T>::candidates_by_reputation();
+		let delegations: Vec<(T::AccountId, T::AccountId, BalanceOf<T>)> = Delegators::<T>::iter()
+			.map(|(delegator, validator, delegation)| (delegator, validator, delegation.amount))
+			.collect();
+
+		let max_winners = T::MaxValidators::get() as usize;
+		let winners = S::elect(candidates.clone(), delegations.clone(), max_winners);
+
+		// the support of a winner is its self-stake plus the delegations to it
+		let supports: Vec<(T::AccountId, Support<T::AccountId>)> = winners
+			.into_iter()
+			.take(max_winners)
+			.map(|winner| {
+				let mut support = Support { total: 0, voters: Vec::new() };
+				let backers = candidates
+					.iter()
+					.filter(|(who, _)| *who == winner)
+					.map(|(who, stake)| (who.clone(), *stake))
+					.chain(
+						delegations
+							.iter()
+							.filter(|(_, validator, _)| *validator == winner)
+							.map(|(delegator, _, amount)| (delegator.clone(), *amount)),
+					);
+				for (backer, amount) in backers {
+					let amount: ExtendedBalance = amount.unique_saturated_into();
+					support.total = support.total.saturating_add(amount);
+					support.voters.push((backer, amount));
+				}
+				(winner, support)
+			})
+			.collect();
+
+		supports.try_into().map_err(|_| "too many winners")
+	}
+}
+
+/// registered validators are the electable targets and every account backing one of them is a single voter, so
+/// `onchain::OnChainExecution` or a multi-phase provider can compute the validator set. the solver may spread a voter's
+/// weight differently over its targets, the stake a validator is slashed and rewarded with is always its `Delegators`
+impl<T: Config> ElectionDataProvider for Pallet<T> {
+	type AccountId = T::AccountId;
+	type BlockNumber = BlockNumberFor<T>;
+	type MaxVotesPerVoter = MaxVotesPerVoter<T>;
+
+	fn electable_targets(bounds: DataProviderBounds) -> data_provider::Result<Vec<T::AccountId>> {
+		let max_targets = bounds.count.map_or(usize::MAX, |count| count.0 as usize);
+		Ok(Pallet::<T>::candidates_by_reputation().into_iter().take(max_targets).map(|(who, _)| who).collect())
+	}
+
+	fn electing_voters(bounds: DataProviderBounds) -> data_provider::Result<Vec<VoterOf<Self>>> {
+		let candidates: Vec<(T::AccountId, BalanceOf<T>)> = PotentialValidators::<T>::iter().collect();
+		let delegations: Vec<(T::AccountId, T::AccountId, BalanceOf<T>)> = Delegators::<T>::iter()
+			.map(|(delegator, validator, delegation)| (delegator, validator, delegation.amount))
+			.collect();
+
+		// stop at the first voter that would exceed the count or the encoded size bound
+		let mut voters: Vec<VoterOf<Self>> = Vec::new();
+		let mut size: u32 = 0;
+		for (who, weight, targets) in ballots(&candidates, &delegations) {
+			let voter: VoterOf<Self> = (who, weight, BoundedVec::truncate_from(targets));
+			let voter_size = size.saturating_add(voter.encoded_size() as u32);
+			if bounds.exhausted(Some(voter_size.into()), Some((voters.len() as u32 + 1).into())) {
+				break;
+			}
+			size = voter_size;
+			voters.push(voter);
+		}
+		Ok(voters)
+	}
+
+	fn desired_targets() -> data_provider::Result<u32> {
+		Ok(T::MaxValidators::get())
+	}
+
+	// elections run at every epoch boundary
+	fn next_election_prediction(now: BlockNumberFor<T>) -> BlockNumberFor<T> {
+		let epoch_duration = T::EpochDuration::get();
+		(now / epoch_duration + 1u32.into()) * epoch_duration
+	}
+}
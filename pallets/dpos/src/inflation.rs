@@ -0,0 +1,67 @@
+// inflation curve the epoch rewards are derived from, a piecewise-linear take on `pallet_staking_reward_curve`
+// driven by the staking ratio, the total `ValidatorStakes` divided by the total issuance
+use crate::pallet::{BalanceOf, Config, Pallet, ValidatorStakes};
+use frame_support::traits::{fungible::Inspect, Get};
+use sp_runtime::{
+	traits::{Saturating, Zero},
+	Perbill,
+};
+
+/// yearly inflation at `staking_ratio`. it grows linearly from `min` at nothing staked to `max` at `ideal`,
+/// then falls linearly back to `min` over the next `falloff` of staking ratio
+pub fn annual_inflation(min: Perbill, max: Perbill, ideal: Perbill, falloff: Perbill, staking_ratio: Perbill) -> Perbill {
+	let range = max.saturating_sub(min);
+	if staking_ratio <= ideal {
+		if ideal.is_zero() {
+			return max;
+		}
+		let progress = Perbill::from_rational(staking_ratio.deconstruct(), ideal.deconstruct());
+		min.saturating_add(progress * range)
+	} else {
+		let over = staking_ratio.saturating_sub(ideal);
+		if over >= falloff {
+			return min;
+		}
+		let decline = Perbill::from_rational(over.deconstruct(), falloff.deconstruct());
+		max.saturating_sub(decline * range)
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// share of the total issuance that is staked, self-stake and delegations of every registered validator
+	pub fn staking_ratio() -> Perbill {
+		let staked = ValidatorStakes::<T>::iter_values().fold(BalanceOf::<T>::zero(), |total, stake| total.saturating_add(stake));
+		let issuance = T::NativeBalance::total_issuance();
+		if issuance.is_zero() {
+			return Perbill::zero();
+		}
+		Perbill::from_rational(staked, issuance)
+	}
+
+	/// yearly inflation at the current staking ratio
+	pub fn current_inflation() -> Perbill {
+		annual_inflation(
+			T::InflationMin::get(),
+			T::InflationMax::get(),
+			T::IdealStakingRatio::get(),
+			T::InflationFalloff::get(),
+			Self::staking_ratio(),
+		)
+	}
+
+	/// total reward of one epoch, the yearly inflation of the total issuance spread over `EpochsPerYear`
+	pub fn epoch_payout() -> BalanceOf<T> {
+		let yearly = Self::current_inflation().mul_floor(T::NativeBalance::total_issuance());
+		yearly / BalanceOf::<T>::from(T::EpochsPerYear::get().max(1))
+	}
+
+	/// yearly return of the staked funds before commission, the inflation divided by the staking ratio.
+	/// saturates at 100%, e.g. when almost nothing is staked
+	pub fn current_apr() -> Perbill {
+		let staking_ratio = Self::staking_ratio();
+		if staking_ratio.is_zero() {
+			return Perbill::one();
+		}
+		Perbill::from_rational(Self::current_inflation().deconstruct(), staking_ratio.deconstruct())
+	}
+}
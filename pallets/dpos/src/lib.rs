@@ -7,6 +7,13 @@ mod mock;
 mod tests;
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
+pub mod apportion;
+pub mod election;
+pub mod inflation;
+pub mod offence;
+pub mod reputation;
+pub mod reward_curve;
+pub mod session;
 
 // https://paritytech.github.io/polkadot-sdk/master/polkadot_sdk_docs/polkadot_sdk/frame_runtime/index.html
 // https://paritytech.github.io/polkadot-sdk/master/polkadot_sdk_docs/guides/your_first_pallet/index.html
@@ -15,32 +22,89 @@ mod benchmarking;
 pub mod pallet {
 	use frame_support::{
 		pallet_prelude::*, 
-		sp_runtime::traits::{Saturating, Zero}, 
+		sp_runtime::{traits::{AccountIdConversion, CheckedDiv, One, Saturating, UniqueSaturatedInto, Zero}, FixedPointNumber, FixedU128, Perbill}, 
 		traits::{
 			fungible::{self, Inspect, Mutate, MutateHold}, 
 			FindAuthor, 
 			BuildGenesisConfig,
-			tokens::{Fortitude, Precision},
-			OriginTrait,
-		} 
+			tokens::{Fortitude, Precision, Preservation},
+			Imbalance, OnUnbalanced,
+		},
+		PalletId,
 	};
 	use frame_system::pallet_prelude::*;
-	use sp_std::prelude::*;
+	use sp_std::{collections::btree_map::BTreeMap, prelude::*};
+	use frame_election_provider_support::ElectionProvider;
+	use sp_staking::SessionIndex;
+	use crate::reward_curve::RewardCurve;
 
 	/// trait to report new validator set to the runtime
 	pub trait ReportNewValidatorSet<AccountId> { 
 		fn report_new_validator_set(_new_set: Vec<AccountId>) {}
 	}
 
+	/// for runtimes that read the validator set through `pallet_session` instead
+	impl<AccountId> ReportNewValidatorSet<AccountId> for () {}
+
+	/// trait to tell the runtime a validator is disabled for the rest of the epoch, e.g. so block production skips it
+	pub trait DisableValidator<AccountId> {
+		fn disable_validator(_who: &AccountId) {}
+	}
+
+	impl<AccountId> DisableValidator<AccountId> for () {}
+
+	/// where the rewards are paid from
+	#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+	pub enum RewardSource {
+		Mint, // every claim mints new tokens
+		Pot, // claims are transferred from the reward pot account, capped by its balance
+	}
+
+	/// `OnUnbalanced` handler that deposits the funds into the reward pot account, e.g. for slashes or fees
+	pub struct RewardPotInflow<T>(PhantomData<T>);
+
+	impl<T: Config> OnUnbalanced<CreditOf<T>> for RewardPotInflow<T> {
+		fn on_nonzero_unbalanced(amount: CreditOf<T>) {
+			// a deposit below the existential deposit of an empty pot is dropped
+			let _ = <T::NativeBalance as fungible::Balanced<T::AccountId>>::resolve(&Pallet::<T>::reward_pot_account(), amount);
+		}
+	}
+
+	/// `OnUnbalanced` fee handler that moves `FeeShare` of each block's fees into the reward pot account and credits it
+	/// to the block author's reward pool of the epoch, the rest goes to `Rest`. `()` burns it
+	pub struct FeeSink<T, Rest = ()>(PhantomData<(T, Rest)>);
+
+	impl<T: Config, Rest: OnUnbalanced<CreditOf<T>>> OnUnbalanced<CreditOf<T>> for FeeSink<T, Rest> {
+		fn on_nonzero_unbalanced(amount: CreditOf<T>) {
+			let Some(author) = Pallet::<T>::find_author() else {
+				Rest::on_unbalanced(amount);
+				return;
+			};
+			let share = T::FeeShare::get().mul_floor(amount.peek());
+			let (share, rest) = amount.split(share);
+			let shared = share.peek();
+			match <T::NativeBalance as fungible::Balanced<T::AccountId>>::resolve(&Pallet::<T>::reward_pot_account(), share) {
+				Ok(()) => {
+					log::debug!("Fees of {:?} credited to the reward pool of {:?}", shared, author);
+					EpochFees::<T>::mutate(&author, |fees| *fees = fees.saturating_add(shared));
+				},
+				// a share below the existential deposit of an empty pot goes to `Rest` as well
+				Err(share) => Rest::on_unbalanced(share),
+			}
+			Rest::on_unbalanced(rest);
+		}
+	}
+
 	/// hold function required for Reason
 	#[pallet::composite_enum]
 	pub enum HoldReason { 
 		ValidatorRegistration, 
 		Delegation,
-		Slashing,
 	}
 
 	pub type BalanceOf<T> = <<T as Config>::NativeBalance as fungible::Inspect<<T as frame_system::Config>::AccountId,>>::Balance;
+	/// imbalance of slashed funds or reward remainders, handed to the `Slash` and `RewardRemainder` handlers
+	pub type CreditOf<T> = fungible::Credit<<T as frame_system::Config>::AccountId, <T as Config>::NativeBalance>;
 
 	#[pallet::pallet]
 	pub struct Pallet<T>(_);
@@ -53,6 +117,73 @@ pub mod pallet {
 		pub amount: BalanceOf<T>,
 		pub epoch_started: BlockNumberFor<T>,
 	}
+
+	/// chunk of stake that is unbonding, it stays held under `reason` until `unlock_epoch` is reached
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen)]
+	#[scale_info(skip_type_params(T))]
+	pub struct UnlockChunk<T:Config> {
+		pub validator: T::AccountId, // validator the stake was unbonded from, so it can still be slashed for it
+		pub reason: HoldReason,
+		pub amount: BalanceOf<T>,
+		pub unlock_epoch: BlockNumberFor<T>,
+	}
+
+	/// stake `redelegate` moved from `from` to `to`, it stays slashable for `from` until `unlock_epoch` like stake unbonding
+	/// from it. a slash takes it from the delegation to `to` first and from the stake unbonding from `to` next
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen)]
+	#[scale_info(skip_type_params(T))]
+	pub struct RedelegationLiability<T:Config> {
+		pub from: T::AccountId,
+		pub to: T::AccountId,
+		pub amount: BalanceOf<T>,
+		pub unlock_epoch: BlockNumberFor<T>,
+	}
+
+	/// slash waiting for its defense window to end, the reporters get `ReporterReward` of the slashed amount once it is applied
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen)]
+	#[scale_info(skip_type_params(T))]
+	pub struct UnappliedSlash<T:Config> {
+		pub fraction: Perbill, // fraction of the stake to slash
+		pub reporters: BoundedVec<T::AccountId, T::MaxReporters>,
+	}
+
+	/// reward pot of a validator for one epoch, paid out lazily through `claim_rewards`
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen)]
+	#[scale_info(skip_type_params(T))]
+	pub struct RewardPot<T:Config> {
+		pub validator_reward: BalanceOf<T>, // validator's commission of the epoch reward
+		pub delegators_reward: BalanceOf<T>, // shared pro-rata between the self-stake and the snapshot delegations
+		pub total_stake: BalanceOf<T>, // stake backing the validator in the epoch, the self-stake plus the earning snapshot delegations
+		pub self_stake: BalanceOf<T>, // validator's self-stake when the pot was recorded
+		pub unpaid: BalanceOf<T>, // claimable part not paid out yet, released from `CommittedRewards` when the pot expires
+	}
+
+	/// reputation of a validator, updated at the end of every epoch it was in `ActiveValidators`
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen, Clone, PartialEq, Eq, RuntimeDebug, Default)]
+	pub struct ValidatorReputation {
+		pub score: Perbill, // combined score, see `reputation::score`
+		pub uptime: Perbill, // average of the authored over the expected blocks, halved each epoch
+		pub tenure: u32, // number of epochs spent in `ActiveValidators`
+		pub slashes: u32, // number of slashes applied to the validator
+	}
+
+	/// where a delegator's claimed rewards go, set with `set_reward_destination`
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen, Clone, PartialEq, Eq, RuntimeDebug, Default)]
+	pub enum RewardDestination<AccountId> {
+		#[default]
+		Free, // paid to the claimer's free balance
+		Compound, // added to the held delegation (or self-stake) the reward was earned with
+		Account(AccountId), // paid to another account's free balance
+	}
+
+	/// progress of the automatic payout of an epoch, the next page starts after `last_delegation`
+	#[derive(TypeInfo, Encode, Decode, MaxEncodedLen)]
+	#[scale_info(skip_type_params(T))]
+	pub struct PayoutProgress<T:Config> {
+		pub epoch: BlockNumberFor<T>,
+		pub last_delegation: Option<(T::AccountId, T::AccountId)>, // (delegator, validator)
+		pub page: u32,
+	}
 	/// Configure the pallet by specifying the parameters and types on which it depends.
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
@@ -67,7 +198,9 @@ pub mod pallet {
 			+ fungible::hold::Inspect<Self::AccountId>
 			+ fungible::hold::Mutate<Self::AccountId>
 			+ fungible::freeze::Inspect<Self::AccountId>
-			+ fungible::freeze::Mutate<Self::AccountId>;
+			+ fungible::freeze::Mutate<Self::AccountId>
+			+ fungible::Balanced<Self::AccountId>
+			+ fungible::BalancedHold<Self::AccountId>;
 
 		/// The maximum number of authorities that the pallet can hold.
 		type MaxValidators: Get<u32>;
@@ -75,15 +208,150 @@ pub mod pallet {
 		/// Find the author of a block. A fake provide for this type is provided in the runtime. You can use a similar mechanism in your tests.
 		type FindAuthor: FindAuthor<Self::AccountId>;
 
+		/// election provider that computes the validator set at the end of each epoch, the pallet is its data provider.
+		/// use `election::StrategyElection` to run one of the pallet's strategies, or `onchain::OnChainExecution`
+		type ElectionProvider: ElectionProvider<
+			AccountId = Self::AccountId,
+			BlockNumber = BlockNumberFor<Self>,
+			DataProvider = Pallet<Self>,
+		>;
+
 		/// Report the new validators to the runtime. This is done through a custom trait defined in this pallet.
 		type ReportNewValidatorSet: ReportNewValidatorSet<Self::AccountId>;
 
+		/// whether `pallet_session` rotates the validators through the pallet's `SessionManager`. the session pallet only
+		/// activates a set one session after it was queued, so `start_session` promotes it to `ActiveValidators`.
+		/// otherwise the elected set is active right away
+		#[pallet::constant]
+		type SessionRotation: Get<bool>;
+
 		/// configurable constant `BlockNumber` to tell us when we should trigger the validator set change. 
 		/// The runtime developer should implement this to represent the time they want validators to change, but for the pallet, we just care about the block number.
 		#[pallet::constant]
 		type EpochDuration: Get<BlockNumberFor<Self>>;
 
 		type RuntimeHoldReason: From<HoldReason>; // defines the type for the hold reason that the runtime should use
+
+		/// number of epochs unbonded stake stays held (and slashable) before it can be withdrawn with `withdraw_unbonded`
+		#[pallet::constant]
+		type UnbondingEpochs: Get<u32>;
+
+		/// maximum number of unlocking chunks an account can have queued at once
+		#[pallet::constant]
+		type MaxUnlockingChunks: Get<u32>;
+
+		/// maximum number of validators a single account can delegate to
+		#[pallet::constant]
+		type MaxDelegationsPerDelegator: Get<u32>;
+
+		/// default minimum self-stake to register as a validator, governance can change it with `set_minimums`
+		#[pallet::constant]
+		type MinValidatorBond: Get<BalanceOf<Self>>;
+
+		/// default minimum amount a delegation must hold, governance can change it with `set_minimums`
+		#[pallet::constant]
+		type MinDelegation: Get<BalanceOf<Self>>;
+
+		/// origin allowed to change the staking parameters of the pallet
+		type AdminOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+
+		/// number of epochs a delegator has to wait between two redelegations
+		#[pallet::constant]
+		type RedelegationCooldown: Get<u32>;
+
+		/// number of epochs rewards can be claimed for, older reward pots and snapshots are removed
+		#[pallet::constant]
+		type HistoryDepth: Get<u32>;
+
+		/// pay out the recorded reward pots automatically, one page per block, instead of only through `claim_rewards`
+		type AutomaticPayouts: Get<bool>;
+
+		/// maximum number of snapshot delegators paid out in one page of the automatic payout
+		#[pallet::constant]
+		type MaxDelegatorsPerPage: Get<u32>;
+
+		/// highest commission a validator can take from its reward pot
+		#[pallet::constant]
+		type MaxCommission: Get<Perbill>;
+
+		/// number of epochs before a commission increase takes effect, decreases apply right away
+		#[pallet::constant]
+		type CommissionChangeDelay: Get<u32>;
+
+		/// number of epochs a slash waits in `UnappliedSlashes` before it is applied, the defense window for appeals
+		#[pallet::constant]
+		type SlashDeferEpochs: Get<u32>;
+
+		/// origin allowed to cancel a deferred slash during the defense window
+		type SlashCancelOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+
+		/// share of each block's fees `FeeSink` credits to the block author's reward pool
+		#[pallet::constant]
+		type FeeShare: Get<Perbill>;
+
+		/// share of its expected blocks an elected validator must author in an epoch, below it the validator is offline
+		#[pallet::constant]
+		type OfflineThreshold: Get<Perbill>;
+
+		/// fraction of the stake slashed when a validator is offline for a second epoch in a row
+		#[pallet::constant]
+		type OfflineSlashFraction: Get<Perbill>;
+
+		/// share of a slashed amount paid to the accounts that reported the offence, split equally between them
+		#[pallet::constant]
+		type ReporterReward: Get<Perbill>;
+
+		/// maximum number of reporters kept for one slash
+		#[pallet::constant]
+		type MaxReporters: Get<u32>;
+
+		/// Tell the runtime a validator is disabled for the rest of the epoch after an offence.
+		type DisableValidator: DisableValidator<Self::AccountId>;
+
+		/// handler for the slashed funds after the reporters are paid, e.g. a treasury. `()` burns them
+		type Slash: OnUnbalanced<CreditOf<Self>>;
+
+		/// handler for the part of an epoch's rewards nobody can claim, e.g. rounding dust. `()` burns it
+		type RewardRemainder: OnUnbalanced<CreditOf<Self>>;
+
+		/// id the reward pot account is derived from
+		#[pallet::constant]
+		type PalletId: Get<PalletId>;
+
+		/// mint the rewards or pay them from the reward pot account
+		type RewardFunding: Get<RewardSource>;
+
+		/// yearly inflation with nothing staked, and the floor the curve falls back to above the ideal staking ratio
+		#[pallet::constant]
+		type InflationMin: Get<Perbill>;
+
+		/// yearly inflation at the ideal staking ratio
+		#[pallet::constant]
+		type InflationMax: Get<Perbill>;
+
+		/// staking ratio, total `ValidatorStakes` over total issuance, the inflation curve peaks at
+		#[pallet::constant]
+		type IdealStakingRatio: Get<Perbill>;
+
+		/// range of staking ratio above the ideal over which the inflation falls back to `InflationMin`
+		#[pallet::constant]
+		type InflationFalloff: Get<Perbill>;
+
+		/// number of epochs in a year, the yearly inflation is spread over them
+		#[pallet::constant]
+		type EpochsPerYear: Get<u32>;
+
+		/// curve that weighs each validator's share of the epoch payout by its share of the total stake,
+		/// use `reward_curve::DecentralizationCurve` to favour smaller validators. `()` leaves the shares unchanged
+		type RewardCurve: RewardCurve;
+
+		/// number of epochs in `ActiveValidators` after which the tenure part of the reputation is full
+		#[pallet::constant]
+		type ReputationTenure: Get<u32>;
+
+		/// share of a validator's reward weight that depends on its reputation score, zero leaves the rewards unchanged
+		#[pallet::constant]
+		type ReputationWeight: Get<Perbill>;
 		
 	}
 
@@ -93,7 +361,7 @@ pub mod pallet {
 	// storagevalue sintax https://paritytech.github.io/polkadot-sdk/master/frame_support/storage/types/struct.StorageValue.html
 	// storagemap syntax https://paritytech.github.io/polkadot-sdk/master/frame_support/storage/types/struct.StorageMap.html 
 	
-	// Current validators storage - bounded vec limited by max validators
+	// Current validators storage - bounded vec limited by max validators, the set elected at the last epoch boundary
 	// ValueQuery will return a default value if the key does not exist
 	#[pallet::storage]
 	pub type CurrentValidators<T: Config> = StorageValue<
@@ -101,6 +369,24 @@ pub mod pallet {
 		QueryKind = ValueQuery
 	>;
 
+	/// validators authoring the current epoch, the per-epoch accounting runs against them. with `SessionRotation`
+	/// they are the set elected an epoch earlier, otherwise the same as `CurrentValidators`
+	#[pallet::storage]
+	pub type ActiveValidators<T: Config> = StorageValue<
+		Value = BoundedVec<T::AccountId, T::MaxValidators>,
+		QueryKind = ValueQuery
+	>;
+
+	/// sets handed to `pallet_session` by `new_session`, keyed by the session they are planned for and promoted to
+	/// `ActiveValidators` when that session starts
+	#[pallet::storage]
+	pub type QueuedValidators<T: Config> = StorageMap<
+		Hasher = Twox64Concat,
+		Key = SessionIndex,
+		Value = BoundedVec<T::AccountId, T::MaxValidators>,
+		QueryKind = OptionQuery
+	>;
+
 	/// OptionQuery will return None if the key does not exist
 	// would like to change this to a BoundedStorageMap if i have time
 	#[pallet::storage]
@@ -112,10 +398,13 @@ pub mod pallet {
 	>; 
 
 	/// represents delegators, whom they delegated their stake to and the delegated amount
+	/// keyed by delegator and validator, so an account can back up to `MaxDelegationsPerDelegator` validators
 	#[pallet::storage]
-	pub type Delegators<T: Config> = StorageMap<
-		Hasher = Blake2_128Concat,
-		Key = T::AccountId, 
+	pub type Delegators<T: Config> = StorageDoubleMap<
+		Hasher1 = Blake2_128Concat,
+		Key1 = T::AccountId, // delegator
+		Hasher2 = Blake2_128Concat,
+		Key2 = T::AccountId, // validator
 		Value = Delegation<T>, 
 		QueryKind = OptionQuery
 	>;
@@ -129,17 +418,102 @@ pub mod pallet {
 		QueryKind = ValueQuery,
 	>;
 
-	/// storage items for epoch tracking and rewards distribution
+	/// where each account's claimed rewards go, `Free` if it never set one
+	#[pallet::storage]
+	pub type RewardDestinations<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = RewardDestination<T::AccountId>,
+		QueryKind = ValueQuery,
+	>;
+
+	/// minimum self-stake to register as a validator, `MinValidatorBond` until governance sets it
+	#[pallet::storage]
+	pub type MinimumValidatorBond<T: Config> = StorageValue<
+		Value = BalanceOf<T>,
+		QueryKind = ValueQuery,
+		OnEmpty = T::MinValidatorBond,
+	>;
+
+	/// minimum amount a delegation must hold, `MinDelegation` until governance sets it
+	#[pallet::storage]
+	pub type MinimumDelegation<T: Config> = StorageValue<
+		Value = BalanceOf<T>,
+		QueryKind = ValueQuery,
+		OnEmpty = T::MinDelegation,
+	>;
 
-	/// snapshot for delegators of winning validators at the beginning of the epoch 
+	/// epoch of the last redelegation of each delegator, used to rate limit `redelegate`
 	#[pallet::storage]
-	pub type SnapshotDelegators<T: Config> = StorageMap<
+	pub type LastRedelegation<T: Config> = StorageMap<
 		Hasher = Blake2_128Concat,
-		Key = T::AccountId, 
+		Key = T::AccountId,
+		Value = BlockNumberFor<T>,
+		QueryKind = OptionQuery,
+	>;
+
+	// storage items for epoch tracking and rewards distribution
+
+	/// snapshot for delegators of winning validators at the beginning of the epoch, kept for `HistoryDepth` epochs
+	#[pallet::storage]
+	pub type SnapshotDelegators<T: Config> = StorageNMap<
+		Key = (
+			NMapKey<Twox64Concat, BlockNumberFor<T>>, // epoch
+			NMapKey<Blake2_128Concat, T::AccountId>, // delegator
+			NMapKey<Blake2_128Concat, T::AccountId>, // validator
+		),
 		Value = Delegation<T>,  
 		QueryKind = OptionQuery
 	>; 
 
+	/// reward pot recorded for each validator at the end of an epoch
+	#[pallet::storage]
+	pub type EpochRewards<T: Config> = StorageDoubleMap<
+		Hasher1 = Twox64Concat,
+		Key1 = BlockNumberFor<T>, // epoch
+		Hasher2 = Blake2_128Concat,
+		Key2 = T::AccountId, // validator
+		Value = RewardPot<T>,
+		QueryKind = OptionQuery
+	>;
+
+	/// reputation score of the validators and its inputs, used in the reward distribution and to break election ties
+	#[pallet::storage]
+	pub type Reputation<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = ValidatorReputation,
+		QueryKind = ValueQuery
+	>;
+
+	/// fees collected by `FeeSink` for each block author in the current epoch, added to its reward pot at the end of the epoch
+	#[pallet::storage]
+	pub type EpochFees<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = BalanceOf<T>,
+		QueryKind = ValueQuery
+	>;
+
+	/// recorded rewards not paid out yet, the part of the reward pot account that is already promised
+	#[pallet::storage]
+	pub type CommittedRewards<T: Config> = StorageValue<
+		Value = BalanceOf<T>,
+		QueryKind = ValueQuery
+	>;
+
+	/// ledger of claimed rewards, keyed by epoch, validator and the account that claimed
+	#[pallet::storage]
+	pub type ClaimedRewards<T: Config> = StorageNMap<
+		Key = (
+			NMapKey<Twox64Concat, BlockNumberFor<T>>,
+			NMapKey<Blake2_128Concat, T::AccountId>,
+			NMapKey<Blake2_128Concat, T::AccountId>,
+		),
+		Value = bool,
+		QueryKind = ValueQuery
+	>;
+
 	/// keep track of the number of blocks authored by each validator
 	#[pallet::storage]
     pub type BlockCount<T: Config> = StorageMap<
@@ -149,6 +523,117 @@ pub mod pallet {
         QueryKind = ValueQuery,
     >;
 
+	/// queue of unlocking chunks for each account, funds are released by `withdraw_unbonded` once they matured
+	#[pallet::storage]
+	pub type Unlocking<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = BoundedVec<UnlockChunk<T>, T::MaxUnlockingChunks>,
+		QueryKind = ValueQuery,
+	>;
+
+	/// stake each account moved with `redelegate` that is still slashable for the validator it left
+	#[pallet::storage]
+	pub type RedelegationLiabilities<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = BoundedVec<RedelegationLiability<T>, T::MaxUnlockingChunks>,
+		QueryKind = ValueQuery,
+	>;
+
+	/// commission each validator takes from its reward pot before the delegators are paid
+	#[pallet::storage]
+	pub type Commissions<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = Perbill,
+		QueryKind = ValueQuery,
+	>;
+
+	/// commission increases waiting to take effect, with the epoch they apply at
+	#[pallet::storage]
+	pub type PendingCommissions<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = (Perbill, BlockNumberFor<T>),
+		QueryKind = OptionQuery,
+	>;
+
+	/// number of consecutive epochs each validator was offline, it decides the penalty of the next offline epoch
+	#[pallet::storage]
+	pub type OfflineStrikes<T: Config> = StorageMap<
+		Hasher = Blake2_128Concat,
+		Key = T::AccountId,
+		Value = u32,
+		QueryKind = ValueQuery,
+	>;
+
+	/// slashes waiting for the defense window to end, keyed by the epoch they apply at and the slashed validator
+	#[pallet::storage]
+	pub type UnappliedSlashes<T: Config> = StorageDoubleMap<
+		Hasher1 = Twox64Concat,
+		Key1 = BlockNumberFor<T>, // epoch the slash is applied at
+		Hasher2 = Blake2_128Concat,
+		Key2 = T::AccountId, // validator
+		Value = UnappliedSlash<T>,
+		QueryKind = OptionQuery,
+	>;
+
+	/// stake of each account exposed to a deferred slash, recorded when the slash is deferred
+	#[pallet::storage]
+	pub type SlashExposures<T: Config> = StorageNMap<
+		Key = (
+			NMapKey<Twox64Concat, BlockNumberFor<T>>, // epoch the slash is applied at
+			NMapKey<Blake2_128Concat, T::AccountId>, // validator
+			NMapKey<Blake2_128Concat, T::AccountId>, // validator or delegator
+		),
+		Value = BalanceOf<T>,
+		QueryKind = ValueQuery,
+	>;
+
+	/// validators disabled by an offence for the rest of the epoch, cleared at the epoch boundary
+	#[pallet::storage]
+	pub type DisabledValidators<T: Config> = StorageValue<
+		Value = BoundedVec<T::AccountId, T::MaxValidators>,
+		QueryKind = ValueQuery,
+	>;
+
+	/// snapshot of the elected validators' commission at the beginning of the epoch, used to split that epoch's rewards
+	#[pallet::storage]
+	pub type SnapshotCommissions<T: Config> = StorageDoubleMap<
+		Hasher1 = Twox64Concat,
+		Key1 = BlockNumberFor<T>, // epoch
+		Hasher2 = Blake2_128Concat,
+		Key2 = T::AccountId, // validator
+		Value = Perbill,
+		QueryKind = OptionQuery
+	>;
+
+	/// snapshot of the elected validators' self-stake at the beginning of the epoch, its share of that epoch's rewards
+	#[pallet::storage]
+	pub type SnapshotSelfStakes<T: Config> = StorageDoubleMap<
+		Hasher1 = Twox64Concat,
+		Key1 = BlockNumberFor<T>, // epoch
+		Hasher2 = Blake2_128Concat,
+		Key2 = T::AccountId, // validator
+		Value = BalanceOf<T>,
+		QueryKind = OptionQuery
+	>;
+
+	/// cursor of the automatic payout that is in progress, the following blocks continue where the last page stopped
+	#[pallet::storage]
+	pub type PayoutCursor<T: Config> = StorageValue<
+		Value = PayoutProgress<T>,
+		QueryKind = OptionQuery
+	>;
+
+	/// last epoch whose automatic payout processed its last page
+	#[pallet::storage]
+	pub type LastFinalizedEpoch<T: Config> = StorageValue<
+		Value = BlockNumberFor<T>,
+		QueryKind = OptionQuery
+	>;
+
 	/// Pallets use events to inform users when important changes are made. https://paritytech.github.io/polkadot-sdk/master/polkadot_sdk_docs/guides/your_first_pallet/index.html#event-and-error
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
@@ -156,11 +641,38 @@ pub mod pallet {
 		ValidatorRegistered {validator: T::AccountId, amount: BalanceOf<T>}, 
 		Delegated {delegator: T::AccountId, validator: T::AccountId, amount: BalanceOf<T>}, // delegator delegated to a validator
 		ValidatorsUpdated,
-		RewardsDistributed,
+		RewardsRecorded { epoch: BlockNumberFor<T>, validator: T::AccountId, amount: BalanceOf<T> }, // reward pot recorded at the end of the epoch
+		RewardsClaimed { who: T::AccountId, validator: T::AccountId, epoch: BlockNumberFor<T>, amount: BalanceOf<T> },
+		RewardDestinationSet { who: T::AccountId, destination: RewardDestination<T::AccountId> },
+		RewardsCompounded { who: T::AccountId, validator: T::AccountId, amount: BalanceOf<T> }, // claimed reward added to the held stake
+		PayoutPageProcessed { epoch: BlockNumberFor<T>, page: u32, delegators: u32 }, // one page of the automatic payout was paid
+		EpochFinalized { epoch: BlockNumberFor<T> }, // last page of the automatic payout was paid
+		PayoutExpired { epoch: BlockNumberFor<T>, page: u32 }, // rewards were pruned before the automatic payout reached the last page
+		CommissionSet { validator: T::AccountId, commission: Perbill },
+		CommissionChangeScheduled { validator: T::AccountId, commission: Perbill, effective_epoch: BlockNumberFor<T> }, // commission increase waiting for the delay
 		Undelegated { delegator: T::AccountId, validator: T::AccountId, amount: BalanceOf<T> },
+		Redelegated { delegator: T::AccountId, from: T::AccountId, to: T::AccountId, amount: BalanceOf<T> }, // stake moved between validators without unbonding
 		ValidatorDeregistered { validator: T::AccountId },
 		DelegatorRemoved { delegator: T::AccountId, validator: T::AccountId, amount: BalanceOf<T> },
-		ValidatorSlashed { validator: T::AccountId, amount: BalanceOf<T> },
+		ValidatorSlashed { validator: T::AccountId, fraction: Perbill, amount: BalanceOf<T> }, // total slashed from the validator and its delegators
+		Slashed { who: T::AccountId, validator: T::AccountId, amount: BalanceOf<T> }, // slashed from one account for the validator's offence
+		SlashDeferred { validator: T::AccountId, fraction: Perbill, apply_epoch: BlockNumberFor<T> }, // slash recorded, applied after the defense window
+		DeferredSlashCancelled { validator: T::AccountId, epoch: BlockNumberFor<T> }, // pending slash removed by governance
+		OfflineWarning { validator: T::AccountId, blocks: u32, expected: u32 }, // first offline epoch in a row
+		OfflineSlashed { validator: T::AccountId, fraction: Perbill }, // second offline epoch in a row, slash deferred
+		OfflineRemoved { validator: T::AccountId }, // third offline epoch in a row, removed from the current validators
+		ValidatorDisabled { validator: T::AccountId }, // disabled by an offence for the rest of the epoch
+		ReporterRewarded { reporter: T::AccountId, amount: BalanceOf<T> }, // share of a slash paid to an offence reporter
+		UnclaimableRewards { epoch: BlockNumberFor<T>, amount: BalanceOf<T> }, // part of the epoch rewards handed to `RewardRemainder`
+		RewardPotDepleted { epoch: BlockNumberFor<T>, requested: BalanceOf<T>, available: BalanceOf<T> }, // epoch rewards scaled down to what the pot can pay
+		UnrecordedFees { epoch: BlockNumberFor<T>, amount: BalanceOf<T> }, // fees of authors without a reward pot handed to `RewardRemainder`
+		ForceSlashed { validator: T::AccountId, fraction: Perbill, amount: BalanceOf<T> }, // slashed by the admin origin
+		ValidatorForceUnregistered { validator: T::AccountId }, // unregistered by the admin origin
+		ForceUndelegated { delegator: T::AccountId, validator: T::AccountId, amount: BalanceOf<T> }, // undelegated by the admin origin
+		Unbonded { who: T::AccountId, validator: T::AccountId, amount: BalanceOf<T>, unlock_epoch: BlockNumberFor<T> }, // new unlocking chunk created
+		Withdrawn { who: T::AccountId, amount: BalanceOf<T> }, // matured unlocking chunks released
+		BondedExtra { validator: T::AccountId, amount: BalanceOf<T> }, // validator added to its self-stake
+		MinimumsSet { min_validator_bond: BalanceOf<T>, min_delegation: BalanceOf<T> }, // governance changed the minimum amounts
 	}
 
 	/// Errors inform users that something went wrong. https://paritytech.github.io/polkadot-sdk/master/polkadot_sdk_docs/guides/your_first_pallet/index.html#event-and-error
@@ -168,11 +680,28 @@ pub mod pallet {
 	pub enum Error<T> {
 		TooManyValidators,
 		InsufficientBalance,
-		AlreadyDelegated,
+		TooManyDelegations,
 		ValidatorNotFound,
 		AlreadyRegistered,
 		NoDelegationFound,
     	InvalidAmount,
+		TooManyUnlockingChunks,
+		TooManyRedelegations,
+		NoUnlockingChunks,
+		NothingToWithdraw,
+		EpochNotFinished,
+		RewardsExpired,
+		NoRewardsForEpoch,
+		RewardsAlreadyClaimed,
+		NothingToClaim,
+		CommissionTooHigh,
+		SameValidator,
+		RedelegationTooSoon,
+		ValidatorBondTooLow,
+		DelegationTooLow,
+		DelegationRemainderTooLow,
+		NoDeferredSlash,
+		SlashPending,
 	} 
 
 	/// Hooks are used to execute code in pallets when certain events occur. 
@@ -183,18 +712,41 @@ pub mod pallet {
 
 			if n % T::EpochDuration::get() == BlockNumberFor::<T>::zero() { // lightweight check at EVERY block, tells us when an Epoch has passed
 				log::debug!("Epoch duration: {:?}", T::EpochDuration::get());
+				// score the validators of the ended epoch before their rewards are weighed with it
+				Self::update_reputation(&ActiveValidators::<T>::get());
 				Self::distribute_epoch_rewards();
+
+				// apply the slashes whose defense window is over before the new validators are elected
+				Self::apply_deferred_slashes();
+
 				log::debug!("Epoch duration met, updating validators.");
+				let ended_validators = ActiveValidators::<T>::get().into_inner();
 				// call update_validators function at the end of each epoch // You cannot return an error here, so you have to be clever with your code...
 				Self::update_validators();
 
-				// Take a snapshot of current validators and delegators at the beginning of each epoch
-				Self::snapshot_validators_delegators();
+				// penalize the validators that missed their slots in the epoch that ended, before the block counts are reset
+				Self::check_offline(ended_validators);
+				DisabledValidators::<T>::kill();
+
+				// apply the commission increases whose delay is over before the new snapshot
+				Self::apply_pending_commissions();
+
+				// the elected set authors the new epoch right away, with `SessionRotation` `start_session` activates
+				// the set queued an epoch earlier instead
+				if !T::SessionRotation::get() {
+					Self::activate_validators(CurrentValidators::<T>::get());
+				}
                 Self::reset_block_counts();
 			}	
 
-			// We return a default weight because we do not expect you to do weights for your project... Except for extra credit...
-			return Weight::default()
+			// continue the automatic payout, one page per block
+			let mut weight = Weight::default();
+			if T::AutomaticPayouts::get() {
+				weight = weight.saturating_add(Self::process_payout_page());
+			}
+
+			// the epoch boundary is not weighed yet, the payout page is since it runs in the following blocks too
+			weight
 		}
 
 		/// Function to increment the block count for the current block author
@@ -217,13 +769,18 @@ pub mod pallet {
 	pub struct GenesisConfig<T: Config> {
 		pub initial_validators: Vec<T::AccountId>,
 		pub initial_balances: Vec<(T::AccountId, BalanceOf<T>)>,
+		pub initial_commission: Perbill,
+		pub reward_pot: BalanceOf<T>, // initial balance of the reward pot account
 	}
 
 	/// Genesis build function to initialize the pallet with the initial validators and their balances
 	#[pallet::genesis_build]
 	impl<T: Config> BuildGenesisConfig for GenesisConfig<T> {
 		fn build(&self) {
-			Pallet::<T>::initialize_validators(self.initial_validators.clone(), self.initial_balances.clone());
+			Pallet::<T>::initialize_validators(self.initial_validators.clone(), self.initial_balances.clone(), self.initial_commission);
+			if !self.reward_pot.is_zero() {
+				let _ = T::NativeBalance::mint_into(&Pallet::<T>::reward_pot_account(), self.reward_pot);
+			}
 		}
 	}
 
@@ -239,9 +796,16 @@ pub mod pallet {
 		pub fn register_validator(
 			origin: OriginFor<T>,
 			amount: BalanceOf<T>,
+			commission: Perbill,
 		)-> DispatchResult {
 			let who = ensure_signed(origin)?; // ensure extrinsic signed by valid account and retrieves the accountID
 
+			// ensure the commission is within the runtime-wide bound
+			ensure!(commission <= T::MaxCommission::get(), Error::<T>::CommissionTooHigh);
+
+			// ensure the self-stake is at least the minimum bond, this is the deposit that protects against spam registrations
+			ensure!(amount >= MinimumValidatorBond::<T>::get(), Error::<T>::ValidatorBondTooLow);
+
 			// ensure caller has enough balance to be a validator
 			ensure!( 
 				T::NativeBalance::balance(&who) >= amount,
@@ -256,54 +820,92 @@ pub mod pallet {
 			T::NativeBalance::hold(&HoldReason::ValidatorRegistration.into(), &who, amount)?; // hold self-stake amount
 			PotentialValidators::<T>::insert(&who, amount); // add caller to list of potential validators
 			ValidatorStakes::<T>::insert(&who, amount); // initialize validator's stake with self-stake
+			Commissions::<T>::insert(&who, commission);
 
 			Self::deposit_event(Event::ValidatorRegistered{validator:who, amount}); // event validator has been registered
 			Ok(())
 		}
 
-		/// function to unregister a validator, function first releases the delegators and then removes the validator
-		pub fn unregister_validator(
+		/// function to change the validator's commission, increases only take effect after `CommissionChangeDelay` epochs
+		pub fn set_commission(
 			origin: OriginFor<T>,
+			commission: Perbill,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-		
-			// ensure caller is a registered validator
-			let self_stake = PotentialValidators::<T>::get(&who).ok_or(Error::<T>::ValidatorNotFound)?;
-			log::debug!("Validator {:?} found with self-stake: {:?}", who, self_stake);
-		
-			// create a vector to store the delegators to be undelegated
-			let mut delegators_to_undelegate: Vec<T::AccountId> = Vec::new();
-		
-			// iterate over all delegators to find those delegating to the validator
-			for (delegator, delegation) in Delegators::<T>::iter() {
-				if delegation.validator == who {
-					log::debug!("Found delegator {:?} with amount {:?} delegating to validator {:?}", delegator, delegation.amount, who);
-					delegators_to_undelegate.push(delegator);
-				}
-			}
-		
-			// call undelegate for each delegator
-			for delegator in delegators_to_undelegate {
-				let delegation = Delegators::<T>::get(&delegator).ok_or(Error::<T>::NoDelegationFound)?;
-				let undelegate_origin = T::RuntimeOrigin::signed(delegator.clone());
-				log::debug!("Undelegating amount {:?} from delegator {:?} for validator {:?}", delegation.amount, delegator, who);
-				Self::undelegate(undelegate_origin, delegation.amount)?;
+
+			ensure!(PotentialValidators::<T>::contains_key(&who), Error::<T>::ValidatorNotFound);
+			ensure!(commission <= T::MaxCommission::get(), Error::<T>::CommissionTooHigh);
+
+			if commission <= Commissions::<T>::get(&who) {
+				// decreases apply right away and replace any pending increase
+				PendingCommissions::<T>::remove(&who);
+				Commissions::<T>::insert(&who, commission);
+				Self::deposit_event(Event::CommissionSet { validator: who, commission });
+			} else {
+				// increases wait for the delay so delegators have time to leave
+				let effective_epoch = Self::current_epoch() + BlockNumberFor::<T>::from(T::CommissionChangeDelay::get());
+				log::debug!("Commission increase to {:?} for validator {:?} scheduled for epoch {:?}", commission, who, effective_epoch);
+				PendingCommissions::<T>::insert(&who, (commission, effective_epoch));
+				Self::deposit_event(Event::CommissionChangeScheduled { validator: who, commission, effective_epoch });
 			}
-		
-			// release self-stake for the validator
-			log::debug!("Releasing self-stake of amount {:?} for validator {:?}", self_stake, who);
-			T::NativeBalance::release(&HoldReason::ValidatorRegistration.into(), &who, self_stake, Precision::BestEffort)?;		
-			// remove the validator from the PotentialValidators storage
-			log::debug!("Removing validator {:?} from PotentialValidators storage", who);
-			PotentialValidators::<T>::remove(&who);		
-			// remove the validator from the ValidatorStakes storage
-			log::debug!("Removing validator {:?} from ValidatorStakes storage", who);
-			ValidatorStakes::<T>::remove(&who);
-		
-			// emit event
-			Self::deposit_event(Event::ValidatorDeregistered { validator: who });
-		
 			Ok(())
+		}
+
+		/// function to add more self-stake to a registered validator
+		pub fn bond_extra(
+			origin: OriginFor<T>,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			ensure!(amount > Zero::zero(), Error::<T>::InvalidAmount);
+			ensure!(PotentialValidators::<T>::contains_key(&who), Error::<T>::ValidatorNotFound);
+			ensure!(T::NativeBalance::balance(&who) >= amount, Error::<T>::InsufficientBalance);
+
+			T::NativeBalance::hold(&HoldReason::ValidatorRegistration.into(), &who, amount)?; // hold the extra self-stake
+
+			// self-stake and total stake move together
+			PotentialValidators::<T>::mutate(&who, |self_stake| {
+				if let Some(self_stake) = self_stake {
+					*self_stake = self_stake.saturating_add(amount);
+				}
+			});
+			ValidatorStakes::<T>::mutate(&who, |stake| *stake = stake.saturating_add(amount));
+
+			Self::deposit_event(Event::BondedExtra { validator: who, amount });
+			Ok(())
+		}
+
+		/// function to unbond part of a validator's self-stake, the rest must stay at or above the minimum bond
+		pub fn unbond_self(
+			origin: OriginFor<T>,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			ensure!(amount > Zero::zero(), Error::<T>::InvalidAmount);
+			let self_stake = PotentialValidators::<T>::get(&who).ok_or(Error::<T>::ValidatorNotFound)?;
+			ensure!(amount <= self_stake, Error::<T>::InsufficientBalance);
+			// to drop below the minimum the validator has to unregister
+			ensure!(
+				self_stake.saturating_sub(amount) >= MinimumValidatorBond::<T>::get(),
+				Error::<T>::ValidatorBondTooLow
+			);
+
+			// the amount stays held under `ValidatorRegistration` until the unbonding period is over
+			Self::unbond(&who, &who, HoldReason::ValidatorRegistration, amount)?;
+
+			PotentialValidators::<T>::insert(&who, self_stake.saturating_sub(amount));
+			ValidatorStakes::<T>::mutate(&who, |stake| *stake = stake.saturating_sub(amount));
+			Ok(())
+		}
+
+		/// function to unregister a validator, function first releases the delegators and then removes the validator
+		pub fn unregister_validator(
+			origin: OriginFor<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::do_unregister_validator(who)
 		}			
 
 		// ---------- delegate and undelegate ----------
@@ -332,32 +934,30 @@ pub mod pallet {
 				Error::<T>::InsufficientBalance
 			);
 
-			let current_epoch = <frame_system::Pallet<T>>::block_number() / T::EpochDuration::get();
-			let next_epoch = current_epoch + BlockNumberFor::<T>::from(1u32);
-			log::debug!("Current epoch: {:?}, Next epoch: {:?}", current_epoch, next_epoch);
+			// ensure the delegation holds at least the minimum after this call
+			let delegated = Delegators::<T>::get(&who, &validator).map(|d| d.amount).unwrap_or_else(Zero::zero);
+			ensure!(
+				delegated.saturating_add(amount) >= MinimumDelegation::<T>::get(),
+				Error::<T>::DelegationTooLow
+			);
 
-			let epoch_started = if CurrentValidators::<T>::get().contains(&validator) {
-				log::debug!("Validator {:?} is already a current validator. Delegation will start in the next epoch: {:?}", validator, next_epoch);
-				next_epoch // If the validator is already a current validator, delegation will start in the next epoch
-			} else {
-				log::debug!("Validator {:?} is not a current validator. Delegation will start in the current epoch: {:?}", validator, current_epoch);
-				current_epoch // If the validator is not a current validator, delegation will start in the current epoch
-			};
+			let epoch_started = Self::delegation_start_epoch(&validator);
 
-			// check if the delegator has already delegated
-			if Delegators::<T>::contains_key(&who) {
-				// if delegating to a different validator, reject the call, else update the existing delegation amount
-				Delegators::<T>::try_mutate_exists(&who, |delegation| {
+			// check if the delegator has already delegated to this validator
+			if Delegators::<T>::contains_key(&who, &validator) {
+				// update the existing delegation amount
+				Delegators::<T>::mutate(&who, &validator, |delegation| {
 					if let Some(delegation) = delegation {
-						if delegation.validator != validator {
-							return Err(Error::<T>::AlreadyDelegated);
-						} else {
-							delegation.amount += amount;
-						}
+						delegation.amount += amount;
 					}
-					Ok(())
-				})?;
+				});
 			} else { // if its a new delegatipn
+				// ensure the delegator does not back more validators than allowed
+				ensure!(
+					(Delegators::<T>::iter_prefix(&who).count() as u32) < T::MaxDelegationsPerDelegator::get(),
+					Error::<T>::TooManyDelegations
+				);
+
 				// create a new delegation entry
 				let delegation = Delegation {
 					validator: validator.clone(),
@@ -365,7 +965,7 @@ pub mod pallet {
 					epoch_started, // Set the epoch when the delegation started
 				};
 				log::debug!("Inserting new delegation for delegator {:?} to validator {:?} starting at epoch {:?}", who, validator, epoch_started);
-				Delegators::<T>::insert(&who, delegation);
+				Delegators::<T>::insert(&who, &validator, delegation);
 			}
 
 			T::NativeBalance::hold(&HoldReason::Delegation.into(), &who, amount)?; // reserve delegation amount
@@ -384,61 +984,271 @@ pub mod pallet {
 		/// function to undelegate stake from a validator
 		pub fn undelegate(
 			origin: OriginFor<T>,
+			validator: T::AccountId,
 			amount: BalanceOf<T>,
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-	
-			// check if delegator has a delegation
-			let delegation = Delegators::<T>::get(&who).ok_or(Error::<T>::NoDelegationFound)?;
-			log::debug!("Delegation found"); 
-			log::debug!("Amount delegated: {:?}", delegation.amount);
-	
-			// ensure amount to undelegate is valid
+			Self::do_undelegate(who, validator, amount)
+		}
+
+
+		/// function to move delegated stake from one validator to another without unbonding, the hold stays in place
+		pub fn redelegate(
+			origin: OriginFor<T>,
+			from: T::AccountId,
+			to: T::AccountId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			ensure!(amount > Zero::zero(), Error::<T>::InvalidAmount);
+			ensure!(from != to, Error::<T>::SameValidator);
+			// stake under review can not be moved out of the slash's reach
+			ensure!(!Self::has_pending_slash(&from), Error::<T>::SlashPending);
+			ensure!(PotentialValidators::<T>::contains_key(&to), Error::<T>::ValidatorNotFound);
+
+			// check if delegator has a delegation to the source validator and enough stake in it
+			let delegation = Delegators::<T>::get(&who, &from).ok_or(Error::<T>::NoDelegationFound)?;
 			ensure!(amount <= delegation.amount, Error::<T>::InsufficientBalance);
-			log::debug!("Amount to undelegate is valid, trying to undelegate {:?}", amount);
+			Self::ensure_delegation_remainder(delegation.amount, amount)?;
 
-			// update the delegator's delegation amount
-			// !!!!!----- nit: you got the delegation already on ln 391, so can mutate and set that instead of making a new call 
-			// to mutate_exists (which doesn't assume the existence of the key)
-			Delegators::<T>::mutate_exists(&who, |maybe_delegation| {
+			// the destination delegation must hold at least the minimum afterwards
+			let delegated = Delegators::<T>::get(&who, &to).map(|d| d.amount).unwrap_or_else(Zero::zero);
+			ensure!(
+				delegated.saturating_add(amount) >= MinimumDelegation::<T>::get(),
+				Error::<T>::DelegationTooLow
+			);
+
+			// rate limit, stake can only hop once every `RedelegationCooldown` epochs
+			let current_epoch = Self::current_epoch();
+			if let Some(last) = LastRedelegation::<T>::get(&who) {
+				ensure!(
+					current_epoch >= last.saturating_add(T::RedelegationCooldown::get().into()),
+					Error::<T>::RedelegationTooSoon
+				);
+			}
+
+			// a new delegation must fit in the limit, unless the source delegation is moved completely
+			let moves_everything = amount == delegation.amount;
+			if !Delegators::<T>::contains_key(&who, &to) && !moves_everything {
+				ensure!(
+					(Delegators::<T>::iter_prefix(&who).count() as u32) < T::MaxDelegationsPerDelegator::get(),
+					Error::<T>::TooManyDelegations
+				);
+			}
+
+			// the moved stake stays slashable for the source validator as long as unbonded stake would
+			Self::add_redelegation_liability(&who, &from, &to, amount)?;
+
+			// take the stake out of the source delegation
+			Delegators::<T>::mutate_exists(&who, &from, |maybe_delegation| {
 				if let Some(delegation) = maybe_delegation {
-					log::debug!("Undelegating: {:?} ...", amount);
-					delegation.amount -= amount;
-					// !!!!!----- Although you have the ensure a few lines above, still better practice to use safe math 
-					// to protect against any regressions introduced in the future which may allow an underflow -----!!!!!
-					log::debug!("Delegation amount after undelegation: {:?}", delegation.amount); // prints zero when trying to undelegate all 
-					
-					if delegation.amount == Zero::zero() {
-						log::debug!("Removing delegator {:?} from storage", who);
+					delegation.amount = delegation.amount.saturating_sub(amount);
+					if delegation.amount.is_zero() {
+						log::debug!("Removing delegation of {:?} to {:?}", who, from);
 						*maybe_delegation = None;
 					}
 				}
 			});
+			ValidatorStakes::<T>::mutate(&from, |stake| *stake = stake.saturating_sub(amount));
 
-			// release the held balance for the delegator
-			log::debug!("Releasing stake");
-			T::NativeBalance::release(&HoldReason::Delegation.into(), &who, amount, Precision::BestEffort)?;
+			// add it to the destination delegation, a new one starts under the same rules as `delegate`
+			let epoch_started = Self::delegation_start_epoch(&to);
+			Delegators::<T>::mutate(&who, &to, |maybe_delegation| match maybe_delegation {
+				Some(delegation) => delegation.amount = delegation.amount.saturating_add(amount),
+				None => *maybe_delegation = Some(Delegation { validator: to.clone(), amount, epoch_started }),
+			});
+			ValidatorStakes::<T>::mutate(&to, |stake| *stake = stake.saturating_add(amount));
 
-			// update the validator's total stake
-			ValidatorStakes::<T>::mutate(&delegation.validator, |stake| {
-				log::debug!("Validator stake before undelegation: {:?}", *stake); 
-				*stake = stake.saturating_sub(amount);
-				log::debug!("Updated validator stake: {:?}", *stake); 
+			LastRedelegation::<T>::insert(&who, current_epoch);
+
+			Self::deposit_event(Event::Redelegated { delegator: who, from, to, amount });
+			Ok(())
+		}
+
+		/// function to release all unlocking chunks of the caller whose unbonding period is over
+		pub fn withdraw_unbonded(
+			origin: OriginFor<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let current_epoch = Self::current_epoch();
+
+			// split the queue into matured chunks and chunks that are still unbonding
+			let mut matured: Vec<(HoldReason, BalanceOf<T>)> = Vec::new();
+			Unlocking::<T>::try_mutate_exists(&who, |maybe_chunks| -> DispatchResult {
+				let chunks = maybe_chunks.as_mut().ok_or(Error::<T>::NoUnlockingChunks)?;
+				chunks.retain(|chunk| {
+					// stake backing a validator with a pending slash stays held until the slash is applied or cancelled
+					if chunk.unlock_epoch <= current_epoch && !Self::has_pending_slash(&chunk.validator) {
+						matured.push((chunk.reason, chunk.amount));
+						false
+					} else {
+						true
+					}
+				});
+				ensure!(!matured.is_empty(), Error::<T>::NothingToWithdraw);
+
+				if chunks.is_empty() {
+					*maybe_chunks = None;
+				}
+				Ok(())
+			})?;
+
+			// release the held balance of every matured chunk
+			let mut withdrawn: BalanceOf<T> = Zero::zero();
+			for (reason, amount) in matured {
+				log::debug!("Releasing matured chunk of {:?} for {:?}", amount, who);
+				let released = T::NativeBalance::release(&reason.into(), &who, amount, Precision::BestEffort)?;
+				withdrawn = withdrawn.saturating_add(released);
+			}
+
+			Self::deposit_event(Event::Withdrawn { who, amount: withdrawn });
+			Ok(())
+		}
+
+		// ---------- rewards ----------
+		/// function to claim the caller's share of a validator's reward pot for a finished epoch
+		pub fn claim_rewards(
+			origin: OriginFor<T>,
+			epoch: BlockNumberFor<T>,
+			validator: T::AccountId,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let current_epoch = Self::current_epoch();
+
+			// rewards can only be claimed for finished epochs that did not expire yet
+			ensure!(epoch < current_epoch, Error::<T>::EpochNotFinished);
+			ensure!(
+				current_epoch - epoch <= BlockNumberFor::<T>::from(T::HistoryDepth::get()),
+				Error::<T>::RewardsExpired
+			);
+			Self::do_claim_rewards(&who, epoch, &validator)
+		}
+
+		/// function to choose where the caller's rewards go, also for the automatic payout
+		pub fn set_reward_destination(
+			origin: OriginFor<T>,
+			destination: RewardDestination<T::AccountId>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			if destination == RewardDestination::Free {
+				RewardDestinations::<T>::remove(&who);
+			} else {
+				RewardDestinations::<T>::insert(&who, destination.clone());
+			}
+
+			Self::deposit_event(Event::RewardDestinationSet { who, destination });
+			Ok(())
+		}
+
+		// ---------- governance ----------
+		/// function to change the minimum validator bond and delegation amount, `None` keeps the current value
+		pub fn set_minimums(
+			origin: OriginFor<T>,
+			min_validator_bond: Option<BalanceOf<T>>,
+			min_delegation: Option<BalanceOf<T>>,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			if let Some(min_validator_bond) = min_validator_bond {
+				MinimumValidatorBond::<T>::put(min_validator_bond);
+			}
+			if let Some(min_delegation) = min_delegation {
+				MinimumDelegation::<T>::put(min_delegation);
+			}
+
+			// existing bonds and delegations below a raised minimum are kept, the minimum applies to the next change
+			Self::deposit_event(Event::MinimumsSet {
+				min_validator_bond: MinimumValidatorBond::<T>::get(),
+				min_delegation: MinimumDelegation::<T>::get(),
 			});
-	
-			Self::deposit_event(Event::Undelegated { delegator: who.clone(), validator: delegation.validator.clone(), amount });
 			Ok(())
 		}
 
+		/// function to cancel a slash that is still in its defense window, e.g. after a successful appeal
+		pub fn cancel_deferred_slash(
+			origin: OriginFor<T>,
+			epoch: BlockNumberFor<T>,
+			validator: T::AccountId,
+		) -> DispatchResult {
+			T::SlashCancelOrigin::ensure_origin(origin)?;
+			ensure!(UnappliedSlashes::<T>::contains_key(epoch, &validator), Error::<T>::NoDeferredSlash);
+
+			log::debug!("Cancelling deferred slash of validator {:?} for epoch {:?}", validator, epoch);
+			UnappliedSlashes::<T>::remove(epoch, &validator);
+			let _ = SlashExposures::<T>::clear_prefix((epoch, &validator), u32::MAX, None);
+
+			Self::deposit_event(Event::DeferredSlashCancelled { validator, epoch });
+			Ok(())
+		}
+
+		/// function to slash a validator and its delegators right away, without the defense window
+		pub fn force_slash(
+			origin: OriginFor<T>,
+			validator: T::AccountId,
+			fraction: Perbill,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			let exposure = Self::live_exposure(&validator);
+			let imbalance = Self::slash_validator(validator.clone(), fraction, exposure)?;
+			let amount = imbalance.peek();
+			T::Slash::on_unbalanced(imbalance);
+			Self::deposit_event(Event::ForceSlashed { validator, fraction, amount });
+			Ok(())
+		}
+
+		/// function to unregister a validator on its behalf, its self-stake and delegations go through the unbonding period
+		pub fn force_unregister_validator(
+			origin: OriginFor<T>,
+			validator: T::AccountId,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			Self::do_unregister_validator(validator.clone())?;
+			Self::deposit_event(Event::ValidatorForceUnregistered { validator });
+			Ok(())
+		}
+
+		/// function to undelegate stake on behalf of a delegator, the amount goes through the unbonding period
+		pub fn force_undelegate(
+			origin: OriginFor<T>,
+			delegator: T::AccountId,
+			validator: T::AccountId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			Self::do_undelegate(delegator.clone(), validator.clone(), amount)?;
+			Self::deposit_event(Event::ForceUndelegated { delegator, validator, amount });
+			Ok(())
+		}
+
+		/// call defer_slash function to record a slash for a validator
+		#[cfg(test)]
+		pub fn test_defer_slash(
+			origin: OriginFor<T>, 
+			validator: T::AccountId,
+			fraction: Perbill,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::defer_slash(validator, fraction, Vec::new(), Self::current_epoch());
+			Ok(())
+		}
 
 		/// call slash_validator function to slash a validator's stake
 		#[cfg(test)]
 		pub fn test_slash_validator(
 			origin: OriginFor<T>, 
-			validator: T::AccountId
+			validator: T::AccountId,
+			fraction: Perbill,
 		) -> DispatchResult {
 			ensure_root(origin)?;
-			Self::slash_validator(validator)
+			let exposure = Self::live_exposure(&validator);
+			let imbalance = Self::slash_validator(validator, fraction, exposure)?;
+			T::Slash::on_unbalanced(imbalance);
+			Ok(())
 		}
 	
 	}
@@ -446,7 +1256,7 @@ pub mod pallet {
 	impl<T: Config> Pallet<T> {
 
 		/// function to initialize genesis set of validators with initial balances
-		pub fn initialize_validators(initial_validators: Vec<T::AccountId>, initial_balances: Vec<(T::AccountId, BalanceOf<T>)>) {
+		pub fn initialize_validators(initial_validators: Vec<T::AccountId>, initial_balances: Vec<(T::AccountId, BalanceOf<T>)>, initial_commission: Perbill) {
             let validators: BoundedVec<T::AccountId, T::MaxValidators> = initial_validators.clone().try_into().expect("Failed to convert validators to BoundedVec");
 			let balance = <BalanceOf<T>>::from(100u32);
 
@@ -457,30 +1267,37 @@ pub mod pallet {
             for validator in validators.iter() {
                 ValidatorStakes::<T>::insert(validator, balance);
                 PotentialValidators::<T>::insert(validator, balance);
+                Commissions::<T>::insert(validator, initial_commission);
             }
 
-            CurrentValidators::<T>::put(validators);
+            CurrentValidators::<T>::put(validators.clone());
+
+			// the genesis set authors the first epoch, the snapshot lets its rewards use the genesis commission
+			Self::activate_validators(validators);
         }
 		
-		// if i have time want to change the sorting mechanism to something more efficient - for now just sort by stake amount 
-		/// function to update the set of validators at the end of each epoch
+		/// function to update the set of validators at the end of each epoch, the configured `ElectionProvider` computes the set
 		pub fn update_validators() {
 			log::debug!("update_validators function called");
 
-			let mut potential_validators: Vec<(T::AccountId, BalanceOf<T>)> = ValidatorStakes::<T>::iter().collect();
-			log::debug!("Potential validators before sorting: {:?}", potential_validators);
-
-			potential_validators.sort_by(|a, b| b.1.cmp(&a.1)); // sort by stake amount in descending order
-			log::debug!("Potential validators after sorting: {:?}", potential_validators);
-
-			// get top validators
-			let max_validators = T::MaxValidators::get() as usize;
-			let new_validators: Result<BoundedVec<T::AccountId, T::MaxValidators>, _> = potential_validators
-				.into_iter()
-				.take(max_validators)
-				.map(|(account_id, _)| account_id) // discards balance
-				.collect::<Vec<_>>()
-				.try_into(); 
+			// get the elected validators, keep the current set if the election failed
+			let supports = match T::ElectionProvider::elect() {
+				Ok(supports) => supports,
+				Err(e) => {
+					log::error!("Election failed: {:?}, keeping the current validators", e);
+					return;
+				},
+			};
+			// the solver's support of a winner can spread its voters' weight differently than their delegations, only the
+			// winners are kept and the stake backing them is always read from `ValidatorStakes` and `Delegators`
+			let mut elected: Vec<T::AccountId> = supports.into_iter().map(|(validator, _)| validator).collect();
+			log::debug!("Elected validators: {:?}", elected);
+			// the provider's bound can be bigger than ours, keep the best backed and break ties with the reputation
+			if elected.len() > T::MaxValidators::get() as usize {
+				elected.sort_by_key(|validator| core::cmp::Reverse((ValidatorStakes::<T>::get(validator), Reputation::<T>::get(validator).score)));
+				elected.truncate(T::MaxValidators::get() as usize);
+			}
+			let new_validators: Result<BoundedVec<T::AccountId, T::MaxValidators>, _> = elected.try_into(); 
 
 			match new_validators {
 				Ok(validators) => {
@@ -496,25 +1313,544 @@ pub mod pallet {
 
 		}
 
-		/// function to take a snapshot of the current validators and delegators at the beginning of each epoch
+		/// function to check that taking `amount` out of a delegation leaves either nothing or at least the minimum delegation
+		fn ensure_delegation_remainder(delegated: BalanceOf<T>, amount: BalanceOf<T>) -> DispatchResult {
+			let remainder = delegated.saturating_sub(amount);
+			ensure!(
+				remainder.is_zero() || remainder >= MinimumDelegation::<T>::get(),
+				Error::<T>::DelegationRemainderTooLow
+			);
+			Ok(())
+		}
+
+		/// function to get the epoch a new delegation to `validator` starts earning rewards in
+		fn delegation_start_epoch(validator: &T::AccountId) -> BlockNumberFor<T> {
+			let current_epoch = Self::current_epoch();
+			let next_epoch = current_epoch + BlockNumberFor::<T>::from(1u32);
+			log::debug!("Current epoch: {:?}, Next epoch: {:?}", current_epoch, next_epoch);
+
+			if CurrentValidators::<T>::get().contains(validator) {
+				log::debug!("Validator {:?} is already a current validator. Delegation will start in the next epoch: {:?}", validator, next_epoch);
+				next_epoch // If the validator is already a current validator, delegation will start in the next epoch
+			} else {
+				log::debug!("Validator {:?} is not a current validator. Delegation will start in the current epoch: {:?}", validator, current_epoch);
+				current_epoch // If the validator is not a current validator, delegation will start in the current epoch
+			}
+		}
+
+		/// function to get the current epoch from the block number
+		pub fn current_epoch() -> BlockNumberFor<T> {
+			<frame_system::Pallet<T>>::block_number() / T::EpochDuration::get()
+		}
+
+		/// function to queue an unlocking chunk for `who`, the amount stays held under `reason` until the unbonding period is over
+		fn unbond(who: &T::AccountId, validator: &T::AccountId, reason: HoldReason, amount: BalanceOf<T>) -> DispatchResult {
+			Self::do_unbond(who, validator, reason, amount, false)
+		}
+
+		/// function to queue an unlocking chunk for `who` that can not fail on a full queue, used when the stake is unbonded
+		/// for `who` by someone else. a full queue gets the amount merged into its last chunk for the same validator, or into
+		/// its last chunk otherwise, and that chunk unlocks with the new one
+		fn unbond_merging(who: &T::AccountId, validator: &T::AccountId, reason: HoldReason, amount: BalanceOf<T>) -> DispatchResult {
+			Self::do_unbond(who, validator, reason, amount, true)
+		}
+
+		/// function to queue the unlocking chunk, shared by `unbond` and `unbond_merging`
+		fn do_unbond(who: &T::AccountId, validator: &T::AccountId, reason: HoldReason, amount: BalanceOf<T>, merge_when_full: bool) -> DispatchResult {
+			let unlock_epoch = Self::current_epoch() + BlockNumberFor::<T>::from(T::UnbondingEpochs::get());
+
+			Unlocking::<T>::try_mutate(who, |chunks| -> DispatchResult {
+				// merge with an existing chunk for the same validator and unlock epoch to keep the queue short
+				if let Some(chunk) = chunks.iter_mut().find(|chunk| {
+					chunk.validator == *validator && chunk.reason == reason && chunk.unlock_epoch == unlock_epoch
+				}) {
+					chunk.amount = chunk.amount.saturating_add(amount);
+					return Ok(());
+				}
+
+				let chunk = UnlockChunk { validator: validator.clone(), reason, amount, unlock_epoch };
+				if chunks.is_full() && merge_when_full {
+					let last = chunks.iter().rposition(|chunk| chunk.validator == *validator && chunk.reason == reason)
+						.or_else(|| chunks.iter().rposition(|chunk| chunk.reason == reason))
+						.unwrap_or(chunks.len() - 1);
+					log::debug!("Unlocking queue of {:?} is full, merging {:?} into chunk {:?}", who, amount, last);
+					let chunk = &mut chunks[last];
+					// a chunk of the other reason takes the amount over with its hold
+					if chunk.reason != reason {
+						T::NativeBalance::release(&reason.into(), who, amount, Precision::Exact)?;
+						T::NativeBalance::hold(&chunk.reason.into(), who, amount)?;
+					}
+					chunk.amount = chunk.amount.saturating_add(amount);
+					chunk.unlock_epoch = chunk.unlock_epoch.max(unlock_epoch);
+					return Ok(());
+				}
+				chunks.try_push(chunk).map_err(|_| Error::<T>::TooManyUnlockingChunks)?;
+				Ok(())
+			})?;
+			log::debug!("Unbonding {:?} for {:?}, can be withdrawn at epoch {:?}", amount, who, unlock_epoch);
+
+			Self::deposit_event(Event::Unbonded { who: who.clone(), validator: validator.clone(), amount, unlock_epoch });
+			Ok(())
+		}
+
+		/// function to record that `amount` of `who`'s stake moved from `from` to `to` and stays slashable for `from`
+		fn add_redelegation_liability(who: &T::AccountId, from: &T::AccountId, to: &T::AccountId, amount: BalanceOf<T>) -> DispatchResult {
+			let current_epoch = Self::current_epoch();
+			let unlock_epoch = current_epoch + BlockNumberFor::<T>::from(T::UnbondingEpochs::get());
+
+			RedelegationLiabilities::<T>::try_mutate(who, |liabilities| -> DispatchResult {
+				// expired liabilities free their slot, one for the same validators and epoch is merged
+				liabilities.retain(|liability| liability.unlock_epoch >= current_epoch);
+				if let Some(liability) = liabilities.iter_mut().find(|liability| {
+					liability.from == *from && liability.to == *to && liability.unlock_epoch == unlock_epoch
+				}) {
+					liability.amount = liability.amount.saturating_add(amount);
+				} else {
+					let liability = RedelegationLiability { from: from.clone(), to: to.clone(), amount, unlock_epoch };
+					liabilities.try_push(liability).map_err(|_| Error::<T>::TooManyRedelegations)?;
+				}
+				Ok(())
+			})
+		}
+
+		/// function to unregister `who`, shared by `unregister_validator` and `force_unregister_validator`. every delegation
+		/// to the validator and its self-stake go into unlocking chunks
+		fn do_unregister_validator(who: T::AccountId) -> DispatchResult {
+			// ensure caller is a registered validator
+			let self_stake = PotentialValidators::<T>::get(&who).ok_or(Error::<T>::ValidatorNotFound)?;
+			log::debug!("Validator {:?} found with self-stake: {:?}", who, self_stake);
+		
+			// create a vector to store the delegators to be undelegated
+			let mut delegators_to_undelegate: Vec<T::AccountId> = Vec::new();
+		
+			// iterate over all delegations to find those delegating to the validator
+			for (delegator, validator, delegation) in Delegators::<T>::iter() {
+				if validator == who {
+					log::debug!("Found delegator {:?} with amount {:?} delegating to validator {:?}", delegator, delegation.amount, who);
+					delegators_to_undelegate.push(delegator);
+				}
+			}
+		
+			// undelegate each delegator fully, a full unlocking queue of a delegator can not block the unregistration
+			for delegator in delegators_to_undelegate {
+				let delegation = Delegators::<T>::take(&delegator, &who).ok_or(Error::<T>::NoDelegationFound)?;
+				log::debug!("Undelegating amount {:?} from delegator {:?} for validator {:?}", delegation.amount, delegator, who);
+				Self::unbond_merging(&delegator, &who, HoldReason::Delegation, delegation.amount)?;
+				Self::deposit_event(Event::Undelegated { delegator, validator: who.clone(), amount: delegation.amount });
+			}
+		
+			// start unbonding self-stake for the validator, it stays held until the unbonding period is over
+			if !self_stake.is_zero() {
+				log::debug!("Unbonding self-stake of amount {:?} for validator {:?}", self_stake, who);
+				Self::unbond_merging(&who, &who, HoldReason::ValidatorRegistration, self_stake)?;
+			}
+			// remove the validator from the PotentialValidators storage
+			log::debug!("Removing validator {:?} from PotentialValidators storage", who);
+			PotentialValidators::<T>::remove(&who);		
+			// remove the validator from the ValidatorStakes storage
+			log::debug!("Removing validator {:?} from ValidatorStakes storage", who);
+			ValidatorStakes::<T>::remove(&who);
+			Commissions::<T>::remove(&who);
+			PendingCommissions::<T>::remove(&who);
+		
+			// emit event
+			Self::deposit_event(Event::ValidatorDeregistered { validator: who });
+		
+			Ok(())
+		}
+
+		/// function to undelegate `amount` of `who`'s delegation to `validator`, shared by `undelegate`, `force_undelegate`
+		/// and the unregistration of the validator. the amount goes into an unlocking chunk
+		fn do_undelegate(who: T::AccountId, validator: T::AccountId, amount: BalanceOf<T>) -> DispatchResult {
+			// check if delegator has a delegation to the validator
+			let delegation = Delegators::<T>::get(&who, &validator).ok_or(Error::<T>::NoDelegationFound)?;
+			log::debug!("Delegation found"); 
+			log::debug!("Amount delegated: {:?}", delegation.amount);
+	
+			// ensure amount to undelegate is valid
+			ensure!(amount <= delegation.amount, Error::<T>::InsufficientBalance);
+			log::debug!("Amount to undelegate is valid, trying to undelegate {:?}", amount);
+
+			// a partial undelegation can not leave less than the minimum behind, undelegate everything instead
+			Self::ensure_delegation_remainder(delegation.amount, amount)?;
+
+			// update the delegator's delegation amount
+			// !!!!!----- nit: you got the delegation already on ln 391, so can mutate and set that instead of making a new call 
+			// to mutate_exists (which doesn't assume the existence of the key)
+			Delegators::<T>::mutate_exists(&who, &validator, |maybe_delegation| {
+				if let Some(delegation) = maybe_delegation {
+					log::debug!("Undelegating: {:?} ...", amount);
+					delegation.amount -= amount;
+					// !!!!!----- Although you have the ensure a few lines above, still better practice to use safe math 
+					// to protect against any regressions introduced in the future which may allow an underflow -----!!!!!
+					log::debug!("Delegation amount after undelegation: {:?}", delegation.amount); // prints zero when trying to undelegate all 
+					
+					if delegation.amount == Zero::zero() {
+						log::debug!("Removing delegator {:?} from storage", who);
+						*maybe_delegation = None;
+					}
+				}
+			});
+
+			// start unbonding the delegated amount, it stays held until the unbonding period is over
+			log::debug!("Unbonding stake");
+			Self::unbond(&who, &delegation.validator, HoldReason::Delegation, amount)?;
+
+			// update the validator's total stake
+			ValidatorStakes::<T>::mutate(&delegation.validator, |stake| {
+				log::debug!("Validator stake before undelegation: {:?}", *stake); 
+				*stake = stake.saturating_sub(amount);
+				log::debug!("Updated validator stake: {:?}", *stake); 
+			});
+	
+			Self::deposit_event(Event::Undelegated { delegator: who.clone(), validator: delegation.validator.clone(), amount });
+			Ok(())
+		}
+
+		/// function to get the stake each account has with `validator` right now, bonded to it, unbonding from it or
+		/// redelegated away from it while still liable for it
+		fn live_exposure(validator: &T::AccountId) -> BTreeMap<T::AccountId, BalanceOf<T>> {
+			let mut exposure: BTreeMap<T::AccountId, BalanceOf<T>> = BTreeMap::new();
+			let mut expose = |who: T::AccountId, amount: BalanceOf<T>| {
+				let total = exposure.entry(who).or_default();
+				*total = total.saturating_add(amount);
+			};
+
+			if let Some(self_stake) = PotentialValidators::<T>::get(validator) {
+				expose(validator.clone(), self_stake);
+			}
+			for (delegator, delegated_to, delegation) in Delegators::<T>::iter() {
+				if delegated_to == *validator {
+					expose(delegator, delegation.amount);
+				}
+			}
+			for (who, chunks) in Unlocking::<T>::iter() {
+				for chunk in chunks.into_iter().filter(|chunk| chunk.validator == *validator) {
+					expose(who.clone(), chunk.amount);
+				}
+			}
+			let current_epoch = Self::current_epoch();
+			for (who, liabilities) in RedelegationLiabilities::<T>::iter() {
+				for liability in liabilities.into_iter().filter(|liability| liability.from == *validator && liability.unlock_epoch >= current_epoch) {
+					expose(who.clone(), liability.amount);
+				}
+			}
+			exposure
+		}
+
+		/// function to get the stake each account had with `validator` at the beginning of `epoch`, from the snapshot
+		/// of the epoch's validators
+		fn snapshot_exposure(epoch: BlockNumberFor<T>, validator: &T::AccountId) -> BTreeMap<T::AccountId, BalanceOf<T>> {
+			let mut exposure: BTreeMap<T::AccountId, BalanceOf<T>> = BTreeMap::new();
+			if let Some(self_stake) = SnapshotSelfStakes::<T>::get(epoch, validator) {
+				exposure.insert(validator.clone(), self_stake);
+			}
+			for ((delegator, delegated_to), delegation) in SnapshotDelegators::<T>::iter_prefix((epoch,)) {
+				if delegated_to == *validator {
+					let total = exposure.entry(delegator).or_default();
+					*total = total.saturating_add(delegation.amount);
+				}
+			}
+			exposure
+		}
+
+		/// function to slash up to `amount` of the stake `who` backed `validator` with. it is taken from the stake still
+		/// with the validator first and from the stake redelegated away from it next. returns the amount slashed
+		fn slash_exposed(who: &T::AccountId, validator: &T::AccountId, amount: BalanceOf<T>, imbalance: &mut CreditOf<T>) -> BalanceOf<T> {
+			let mut total = Self::slash_stake_with(who, validator, amount, imbalance);
+			let mut rest = amount.saturating_sub(total);
+			if rest.is_zero() {
+				return total;
+			}
+
+			let current_epoch = Self::current_epoch();
+			RedelegationLiabilities::<T>::mutate_exists(who, |maybe_liabilities| {
+				if let Some(liabilities) = maybe_liabilities {
+					for liability in liabilities.iter_mut().filter(|liability| liability.from == *validator && liability.unlock_epoch >= current_epoch) {
+						let taken = Self::slash_stake_with(who, &liability.to, rest.min(liability.amount), imbalance);
+						log::debug!("Slashed redelegated stake of {:?} by {:?} for validator {:?}", who, taken, validator);
+						liability.amount = liability.amount.saturating_sub(taken);
+						rest = rest.saturating_sub(taken);
+						total = total.saturating_add(taken);
+					}
+					liabilities.retain(|liability| !liability.amount.is_zero() && liability.unlock_epoch >= current_epoch);
+
+					if liabilities.is_empty() {
+						*maybe_liabilities = None;
+					}
+				}
+			});
+			total
+		}
+
+		/// function to slash up to `amount` of `who`'s stake with `validator`, the self-stake or delegation first and the
+		/// stake unbonding from the validator next. returns the amount slashed
+		fn slash_stake_with(who: &T::AccountId, validator: &T::AccountId, amount: BalanceOf<T>, imbalance: &mut CreditOf<T>) -> BalanceOf<T> {
+			let bonded = if who == validator {
+				let self_stake = PotentialValidators::<T>::get(who).unwrap_or_else(Zero::zero);
+				let slashed = Self::slash_held(HoldReason::ValidatorRegistration, who, amount.min(self_stake), imbalance);
+				PotentialValidators::<T>::mutate(who, |maybe_self_stake| {
+					if let Some(self_stake) = maybe_self_stake {
+						*self_stake = self_stake.saturating_sub(slashed);
+					}
+				});
+				slashed
+			} else {
+				let delegated = Delegators::<T>::get(who, validator).map(|delegation| delegation.amount).unwrap_or_else(Zero::zero);
+				let slashed = Self::slash_held(HoldReason::Delegation, who, amount.min(delegated), imbalance);
+				Delegators::<T>::mutate_exists(who, validator, |maybe_delegation| {
+					if let Some(delegation) = maybe_delegation {
+						delegation.amount = delegation.amount.saturating_sub(slashed);
+						if delegation.amount.is_zero() {
+							*maybe_delegation = None;
+						}
+					}
+				});
+				slashed
+			};
+			if !bonded.is_zero() {
+				ValidatorStakes::<T>::mutate(validator, |stake| *stake = stake.saturating_sub(bonded));
+			}
+
+			let mut total = bonded;
+			let mut rest = amount.saturating_sub(bonded);
+			if !rest.is_zero() {
+				Unlocking::<T>::mutate_exists(who, |maybe_chunks| {
+					if let Some(chunks) = maybe_chunks {
+						for chunk in chunks.iter_mut().filter(|chunk| chunk.validator == *validator) {
+							let taken = Self::slash_held(chunk.reason, who, rest.min(chunk.amount), imbalance);
+							chunk.amount = chunk.amount.saturating_sub(taken);
+							rest = rest.saturating_sub(taken);
+							total = total.saturating_add(taken);
+						}
+						chunks.retain(|chunk| !chunk.amount.is_zero());
+
+						if chunks.is_empty() {
+							*maybe_chunks = None;
+						}
+					}
+				});
+			}
+			total
+		}
+
+		/// function to make `validators` the set authoring the current epoch and take the epoch's snapshot of them
+		pub(crate) fn activate_validators(validators: BoundedVec<T::AccountId, T::MaxValidators>) {
+			log::debug!("Validators {:?} active in epoch {:?}", validators, Self::current_epoch());
+			ActiveValidators::<T>::put(validators);
+			Self::snapshot_validators_delegators();
+		}
+
+		/// function to take a snapshot of the active validators and delegators at the beginning of each epoch
 		fn snapshot_validators_delegators() {
 			log::debug!("snapshot_validators_delegators function called");
 
-			// take a snapshot of the current validators
-			let current_validators = CurrentValidators::<T>::get();
+			// take a snapshot of the validators authoring the epoch
+			let current_validators = ActiveValidators::<T>::get();
+			let current_epoch = Self::current_epoch();
 
 			// take a snapshot of the current delegators
 			for validator in current_validators.iter() {
-                for (delegator, delegation) in Delegators::<T>::iter() {
-                    if delegation.validator == *validator {
+				SnapshotCommissions::<T>::insert(current_epoch, validator, Commissions::<T>::get(validator));
+				SnapshotSelfStakes::<T>::insert(current_epoch, validator, PotentialValidators::<T>::get(validator).unwrap_or_else(Zero::zero));
+                for (delegator, delegated_validator, delegation) in Delegators::<T>::iter() {
+                    if delegated_validator == *validator {
 						// log::debug!("Found delegator {:?} for validator {:?} with delegation amount {:?}", delegator, validator, delegation.amount);
-                        SnapshotDelegators::<T>::insert(&delegator, delegation);
+                        SnapshotDelegators::<T>::insert((current_epoch, &delegator, validator), delegation);
                     }
                 }
             }
 			log::debug!("Snapshot taken for validators and delegators");
 		}
 
+		/// function to apply the pending commission increases whose delay is over
+		fn apply_pending_commissions() {
+			let current_epoch = Self::current_epoch();
+			let due: Vec<(T::AccountId, Perbill)> = PendingCommissions::<T>::iter()
+				.filter(|(_, (_, effective_epoch))| *effective_epoch <= current_epoch)
+				.map(|(validator, (commission, _))| (validator, commission))
+				.collect();
+
+			for (validator, commission) in due {
+				log::debug!("Applying commission {:?} for validator {:?}", commission, validator);
+				PendingCommissions::<T>::remove(&validator);
+				Commissions::<T>::insert(&validator, commission);
+				Self::deposit_event(Event::CommissionSet { validator, commission });
+			}
+		}
+
+		/// function to record a slash in `UnappliedSlashes`, it is applied `SlashDeferEpochs` epochs later unless
+		/// `SlashCancelOrigin` cancels it. a second slash for the same validator and epoch keeps the higher fraction
+		/// and adds its reporters. the stake exposed to it is the one that backed the validator in `offence_epoch`:
+		/// the epoch's snapshot if the validator was active in it, otherwise the live stake for an offence in the
+		/// current epoch. it is recorded in `SlashExposures`, so stake that joins during the defense window is not slashed
+		pub(crate) fn defer_slash(validator: T::AccountId, fraction: Perbill, reporters: Vec<T::AccountId>, offence_epoch: BlockNumberFor<T>) {
+			let current_epoch = Self::current_epoch();
+			let exposure = if SnapshotSelfStakes::<T>::contains_key(offence_epoch, &validator) {
+				Self::snapshot_exposure(offence_epoch, &validator)
+			} else if offence_epoch == current_epoch {
+				Self::live_exposure(&validator)
+			} else {
+				BTreeMap::new()
+			};
+			if exposure.is_empty() {
+				// e.g. the snapshot of the offence epoch was pruned already
+				log::debug!("No stake of validator {:?} known for epoch {:?}, slash ignored", validator, offence_epoch);
+				return;
+			}
+
+			let apply_epoch = current_epoch + BlockNumberFor::<T>::from(T::SlashDeferEpochs::get());
+			for (who, amount) in exposure {
+				SlashExposures::<T>::mutate((apply_epoch, &validator, &who), |exposed| *exposed = (*exposed).max(amount));
+			}
+
+			UnappliedSlashes::<T>::mutate(apply_epoch, &validator, |pending| {
+				let slash = pending.get_or_insert_with(|| UnappliedSlash { fraction, reporters: BoundedVec::new() });
+				slash.fraction = slash.fraction.max(fraction);
+				for reporter in reporters {
+					if !slash.reporters.contains(&reporter) && slash.reporters.try_push(reporter).is_err() {
+						log::debug!("Too many reporters for the slash of validator {:?}", validator);
+						break;
+					}
+				}
+			});
+			log::debug!("Slash of {:?} for validator {:?} deferred to epoch {:?}", fraction, validator, apply_epoch);
+			Self::deposit_event(Event::SlashDeferred { validator: validator.clone(), fraction, apply_epoch });
+
+			// without a defense window the slash lands right away
+			if T::SlashDeferEpochs::get() == 0 {
+				Self::apply_deferred_slashes();
+			}
+		}
+
+		/// function to apply the deferred slashes whose defense window is over
+		fn apply_deferred_slashes() {
+			let current_epoch = Self::current_epoch();
+			let due: Vec<(BlockNumberFor<T>, T::AccountId, UnappliedSlash<T>)> = UnappliedSlashes::<T>::iter()
+				.filter(|(apply_epoch, _, _)| *apply_epoch <= current_epoch)
+				.collect();
+
+			for (apply_epoch, validator, slash) in due {
+				UnappliedSlashes::<T>::remove(apply_epoch, &validator);
+				let exposure: BTreeMap<T::AccountId, BalanceOf<T>> = SlashExposures::<T>::drain_prefix((apply_epoch, &validator)).collect();
+				log::debug!("Applying deferred slash of {:?} for validator {:?}", slash.fraction, validator);
+				// the stake may be gone already, e.g. slashed completely by an earlier entry
+				match Self::slash_validator(validator, slash.fraction, exposure) {
+					Ok(imbalance) => T::Slash::on_unbalanced(Self::reward_reporters(slash.reporters.into_inner(), imbalance)),
+					Err(e) => log::error!("Failed to apply deferred slash: {:?}", e),
+				}
+			}
+		}
+
+		/// function to pay `ReporterReward` of the slashed funds to the reporters of the offence, split equally.
+		/// returns what is left for the `Slash` handler
+		fn reward_reporters(reporters: Vec<T::AccountId>, mut imbalance: CreditOf<T>) -> CreditOf<T> {
+			if reporters.is_empty() {
+				return imbalance;
+			}
+			let reward = T::ReporterReward::get() * imbalance.peek();
+			let per_reporter = reward / BalanceOf::<T>::from(reporters.len() as u32);
+			if per_reporter.is_zero() {
+				return imbalance;
+			}
+
+			for reporter in reporters {
+				let (reporter_reward, rest) = imbalance.split(per_reporter);
+				imbalance = rest;
+				match <T::NativeBalance as fungible::Balanced<T::AccountId>>::resolve(&reporter, reporter_reward) {
+					Ok(()) => Self::deposit_event(Event::ReporterRewarded { reporter, amount: per_reporter }),
+					Err(reporter_reward) => {
+						// e.g. below the existential deposit, the reward stays with the slashed funds
+						log::error!("Failed to reward reporter {:?}", reporter);
+						imbalance.subsume(reporter_reward);
+					},
+				}
+			}
+			imbalance
+		}
+
+		/// function to slash up to `amount` of the funds `who` holds under `reason`, the funds are added to `imbalance`.
+		/// returns the amount slashed
+		fn slash_held(reason: HoldReason, who: &T::AccountId, amount: BalanceOf<T>, imbalance: &mut CreditOf<T>) -> BalanceOf<T> {
+			let (credit, _) = <T::NativeBalance as fungible::BalancedHold<T::AccountId>>::slash(&reason.into(), who, amount);
+			let slashed = credit.peek();
+			imbalance.subsume(credit);
+			slashed
+		}
+
+		/// function to disable a validator for the rest of the epoch and tell the runtime about it
+		pub(crate) fn disable_validator(validator: &T::AccountId) {
+			let disabled = DisabledValidators::<T>::mutate(|disabled| {
+				!disabled.contains(validator) && disabled.try_push(validator.clone()).is_ok()
+			});
+			if disabled {
+				log::debug!("Validator {:?} disabled until the end of the epoch", validator);
+				T::DisableValidator::disable_validator(validator);
+				Self::deposit_event(Event::ValidatorDisabled { validator: validator.clone() });
+			}
+		}
+
+		/// function to check the validators of the epoch that just ended for downtime. a validator that authored less than
+		/// `OfflineThreshold` of its share of `EpochDuration` gets a strike and the penalty grows with consecutive strikes:
+		/// a warning, then a deferred slash of `OfflineSlashFraction`, then removal from the new `CurrentValidators`
+		fn check_offline(ended_validators: Vec<T::AccountId>) {
+			if ended_validators.is_empty() {
+				return;
+			}
+			let epoch_blocks: u32 = T::EpochDuration::get().unique_saturated_into();
+			let expected = epoch_blocks / ended_validators.len() as u32;
+			let min_blocks = T::OfflineThreshold::get() * expected;
+
+			let mut removed: Vec<T::AccountId> = Vec::new();
+			let disabled = DisabledValidators::<T>::get();
+			for validator in ended_validators {
+				// a disabled validator is not allowed to author, it was punished for its offence already
+				if disabled.contains(&validator) {
+					continue;
+				}
+				let blocks = BlockCount::<T>::get(&validator);
+				if blocks >= min_blocks {
+					// an epoch online clears the strikes
+					OfflineStrikes::<T>::remove(&validator);
+					continue;
+				}
+
+				let strikes = OfflineStrikes::<T>::mutate(&validator, |strikes| {
+					*strikes = strikes.saturating_add(1);
+					*strikes
+				});
+				log::debug!("Validator {:?} authored {:?} of {:?} expected blocks, strike {:?}", validator, blocks, expected, strikes);
+
+				match strikes {
+					1 => Self::deposit_event(Event::OfflineWarning { validator, blocks, expected }),
+					2 => {
+						let fraction = T::OfflineSlashFraction::get();
+						Self::deposit_event(Event::OfflineSlashed { validator: validator.clone(), fraction });
+						// the validator missed its slots in the epoch that just ended
+						Self::defer_slash(validator, fraction, Vec::new(), Self::current_epoch().saturating_sub(One::one()));
+					},
+					_ => {
+						Self::deposit_event(Event::OfflineRemoved { validator: validator.clone() });
+						removed.push(validator);
+					},
+				}
+			}
+
+			if !removed.is_empty() {
+				// the election already ran, take the offline validators out of the new set and report it again
+				let mut validators = CurrentValidators::<T>::get();
+				validators.retain(|validator| !removed.contains(validator));
+				log::debug!("Removed offline validators, new validators: {:?}", validators);
+				CurrentValidators::<T>::put(validators.clone());
+				T::ReportNewValidatorSet::report_new_validator_set(validators.into());
+			}
+		}
+
+		/// function to check whether a slash for `validator` is still in its defense window
+		fn has_pending_slash(validator: &T::AccountId) -> bool {
+			UnappliedSlashes::<T>::iter_keys().any(|(_, slashed)| slashed == *validator)
+		}
+
 		/// function to keep track of the number of blocks authored by each validator
 		fn reset_block_counts() {
             log::debug!("reset_block_counts function called");
@@ -523,94 +1859,430 @@ pub mod pallet {
             }
         }
 
-		/// function to distribute rewards to validators and delegators at the end of each epoch
-		fn distribute_epoch_rewards() {
-			log::debug!("Distribute epoch rewards function called");
-	
-			let epoch_validators = CurrentValidators::<T>::get(); // here snapshot validators
-			let current_epoch = <frame_system::Pallet<T>>::block_number() / T::EpochDuration::get();
-			log::debug!("Current epoch: {:?}", current_epoch);
+		/// function to pay `who` its share of a validator's reward pot and record the claim in the ledger
+		fn do_claim_rewards(who: &T::AccountId, epoch: BlockNumberFor<T>, validator: &T::AccountId) -> DispatchResult {
+			let pot = EpochRewards::<T>::get(epoch, validator).ok_or(Error::<T>::NoRewardsForEpoch)?;
+			ensure!(
+				!ClaimedRewards::<T>::get((epoch, validator, who)),
+				Error::<T>::RewardsAlreadyClaimed
+			);
 
-			for validator in epoch_validators.iter() {
-				let block_count = BlockCount::<T>::get(&validator);
-	
-				// Total reward amount for the epoch, now multiplied by the block count
-				let base_reward_per_block = BalanceOf::<T>::from(1000u32);
-            	let total_reward = base_reward_per_block.saturating_mul(BalanceOf::<T>::from(block_count));
-				log::debug!("Validator {:?} authored {:?} blocks and has a total reward pool of {:?}", validator, block_count, total_reward);
-	
-				// Allocate a fixed percentage to the validator (e.g., 30%)
-				let validator_percentage: BalanceOf<T> = BalanceOf::<T>::from(30u32);
-				let hundred: BalanceOf<T> = BalanceOf::<T>::from(100u32);
-	
-				let validator_reward = total_reward.saturating_mul(validator_percentage) / hundred;
-				let delegators_reward_pool = total_reward.saturating_sub(validator_reward);
-	
-				// Retrieve validator stake 
-				let validator_stake = ValidatorStakes::<T>::get(&validator);
-				log::debug!("Validator {:?} has a total stake of: {:?}", validator, validator_stake);
-	
-				let mut remaining_delegators_reward = delegators_reward_pool;
-	
-				// Iterate over all epoch delegators and distribute rewards 
-				for (delegator, delegation) in SnapshotDelegators::<T>::iter() {
-					log::debug!("Checking delegator {:?} who delegated to validator {:?} starting at epoch {:?}", delegator, delegation.validator, delegation.epoch_started);
-					if delegation.validator == *validator && delegation.epoch_started < current_epoch{
-						let delegator_reward = delegators_reward_pool.saturating_mul(delegation.amount) / validator_stake;
-						remaining_delegators_reward = remaining_delegators_reward.saturating_sub(delegator_reward);
-	
-						// Log the delegator reward details
-						log::debug!("Delegator {:?} has delegated {:?} to validator {:?} and receives a reward of {:?}", delegator, delegation.amount, validator, delegator_reward);
-						Self::deposit_event(Event::RewardsDistributed);
-	
-						// Attempt to mint the reward to the delegator's account
-						if let Err(e) = T::NativeBalance::mint_into(&delegator, delegator_reward) {
-							log::error!("Failed to mint reward for delegator: {:?}", e);
-						}
+			// validator's commission and the share of its self-stake
+			let mut reward: BalanceOf<T> = Zero::zero();
+			if who == validator {
+				let self_stake_reward = crate::apportion::share(pot.delegators_reward, pot.self_stake, pot.total_stake);
+				reward = reward.saturating_add(pot.validator_reward).saturating_add(self_stake_reward);
+			}
+
+			// delegator's share, only for delegations that were in the snapshot and started before the epoch ended
+			if let Some(delegation) = SnapshotDelegators::<T>::get((epoch, who, validator)) {
+				if delegation.epoch_started <= epoch {
+					let delegator_reward = crate::apportion::share(pot.delegators_reward, delegation.amount, pot.total_stake);
+					log::debug!("Delegator {:?} has delegated {:?} to validator {:?} and receives a reward of {:?}", who, delegation.amount, validator, delegator_reward);
+					reward = reward.saturating_add(delegator_reward);
+				}
+			}
+			ensure!(!reward.is_zero(), Error::<T>::NothingToClaim);
+
+			let destination = RewardDestinations::<T>::get(who);
+			let payee = match &destination {
+				RewardDestination::Account(payee) => payee,
+				_ => who,
+			};
+			match T::RewardFunding::get() {
+				RewardSource::Mint => {
+					T::NativeBalance::mint_into(payee, reward)?;
+				},
+				RewardSource::Pot => {
+					T::NativeBalance::transfer(&Self::reward_pot_account(), payee, reward, Preservation::Preserve)?;
+				},
+			}
+			if destination == RewardDestination::Compound {
+				Self::compound_reward(who, validator, reward);
+			}
+			ClaimedRewards::<T>::insert((epoch, validator, who), true);
+			EpochRewards::<T>::mutate(epoch, validator, |pot| {
+				if let Some(pot) = pot {
+					pot.unpaid = pot.unpaid.saturating_sub(reward);
+				}
+			});
+			CommittedRewards::<T>::mutate(|committed| *committed = committed.saturating_sub(reward));
+
+			Self::deposit_event(Event::RewardsClaimed { who: who.clone(), validator: validator.clone(), epoch, amount: reward });
+			Ok(())
+		}
+
+		/// function to add a claimed reward to the stake it was earned with, the self-stake for the validator and the
+		/// delegation for a delegator. it stays free if that stake is gone or the reward can not be held, so the
+		/// automatic payout never pays a reward without marking it claimed
+		fn compound_reward(who: &T::AccountId, validator: &T::AccountId, reward: BalanceOf<T>) {
+			if who == validator {
+				if !PotentialValidators::<T>::contains_key(who) {
+					return;
+				}
+				if let Err(e) = T::NativeBalance::hold(&HoldReason::ValidatorRegistration.into(), who, reward) {
+					log::debug!("Reward of validator {:?} not compounded: {:?}", who, e);
+					return;
+				}
+				PotentialValidators::<T>::mutate(who, |self_stake| {
+					if let Some(self_stake) = self_stake {
+						*self_stake = self_stake.saturating_add(reward);
 					}
+				});
+			} else {
+				if !Delegators::<T>::contains_key(who, validator) {
+					return;
 				}
-	
-				log::debug!("Validator {:?} receives the reward of {:?}", validator, validator_reward);
-	
-				// Credit the remaining amount to the validator
-				if let Err(e) = T::NativeBalance::mint_into(&validator, validator_reward) {
-					log::error!("Failed to mint reward for validator: {:?}", e);
+				if let Err(e) = T::NativeBalance::hold(&HoldReason::Delegation.into(), who, reward) {
+					log::debug!("Reward of delegator {:?} not compounded: {:?}", who, e);
+					return;
 				}
-	
-				log::debug!("Rewards distributed for validator {:?}: {:?}", validator, validator_reward);
-				Self::deposit_event(Event::RewardsDistributed);
+				Delegators::<T>::mutate(who, validator, |delegation| {
+					if let Some(delegation) = delegation {
+						delegation.amount = delegation.amount.saturating_add(reward);
+					}
+				});
 			}
+			ValidatorStakes::<T>::mutate(validator, |stake| *stake = stake.saturating_add(reward));
+
+			Self::deposit_event(Event::RewardsCompounded { who: who.clone(), validator: validator.clone(), amount: reward });
 		}
 
-		/// Internal function to slash a validator's stake.
-		#[allow(dead_code)]
-		fn slash_validator(validator: T::AccountId) -> DispatchResult {
-			// Ensure the validator is a potential validator
-			ensure!(
-				PotentialValidators::<T>::contains_key(&validator), 
-				Error::<T>::ValidatorNotFound
-			);
-	
-			// Get the validator's total stake
-			let validator_stake = ValidatorStakes::<T>::get(&validator);
-			ensure!(
-				!validator_stake.is_zero(), 
-				Error::<T>::InsufficientBalance
-			);
+		/// function to pay out one page of the oldest finished epoch that is not finalized yet
+		/// returns the weight of the page
+		fn process_payout_page() -> Weight {
+			let current_epoch = Self::current_epoch();
+			let oldest_epoch = current_epoch.saturating_sub(BlockNumberFor::<T>::from(T::HistoryDepth::get()));
+			let db_weight = T::DbWeight::get();
+			let mut weight = db_weight.reads(1);
+
+			// continue the payout in progress or start with the next finished epoch
+			let mut progress = match PayoutCursor::<T>::get() {
+				Some(progress) if progress.epoch < oldest_epoch => {
+					// the rewards of the epoch were pruned before its last page, the rest of it can not be paid anymore
+					log::debug!("Automatic payout of epoch {:?} expired after {:?} pages", progress.epoch, progress.page);
+					PayoutCursor::<T>::kill();
+					LastFinalizedEpoch::<T>::put(progress.epoch);
+					Self::deposit_event(Event::PayoutExpired { epoch: progress.epoch, page: progress.page });
+					return weight.saturating_add(db_weight.writes(2));
+				},
+				Some(progress) => progress,
+				None => {
+					weight = weight.saturating_add(db_weight.reads(1));
+					let next_epoch = LastFinalizedEpoch::<T>::get()
+						.map(|epoch| epoch + One::one())
+						.unwrap_or_else(Zero::zero)
+						.max(oldest_epoch);
+					if next_epoch >= current_epoch {
+						return weight; // nothing to pay out
+					}
+					PayoutProgress { epoch: next_epoch, last_delegation: None, page: 0 }
+				},
+			};
+			let epoch = progress.epoch;
+
+			// the validators' cuts are paid with the first page
+			if progress.page == 0 {
+				for (validator, _) in EpochRewards::<T>::iter_prefix(epoch) {
+					weight = weight.saturating_add(db_weight.reads(1)).saturating_add(Self::claim_weight());
+					if let Err(e) = Self::do_claim_rewards(&validator, epoch, &validator) {
+						log::debug!("No payout for validator {:?} in epoch {:?}: {:?}", validator, epoch, e);
+					}
+				}
+			}
+
+			// read the next page of snapshot delegations, starting after the last one paid
+			let mut delegations = match &progress.last_delegation {
+				Some((delegator, validator)) => SnapshotDelegators::<T>::iter_prefix_from(
+					(epoch,),
+					SnapshotDelegators::<T>::hashed_key_for((epoch, delegator, validator)),
+				),
+				None => SnapshotDelegators::<T>::iter_prefix((epoch,)),
+			};
+			let page: Vec<(T::AccountId, T::AccountId)> = delegations
+				.by_ref()
+				.take(T::MaxDelegatorsPerPage::get() as usize)
+				.map(|(delegation_key, _)| delegation_key)
+				.collect();
+			let finished = delegations.next().is_none();
+			weight = weight.saturating_add(db_weight.reads(page.len() as u64 + 1));
+
+			for (delegator, validator) in page.iter() {
+				weight = weight.saturating_add(Self::claim_weight());
+				if let Err(e) = Self::do_claim_rewards(delegator, epoch, validator) {
+					log::debug!("No payout for delegator {:?} in epoch {:?}: {:?}", delegator, epoch, e);
+				}
+			}
+			Self::deposit_event(Event::PayoutPageProcessed { epoch, page: progress.page, delegators: page.len() as u32 });
+
+			if finished {
+				log::debug!("Automatic payout of epoch {:?} finished after {:?} pages", epoch, progress.page + 1);
+				PayoutCursor::<T>::kill();
+				LastFinalizedEpoch::<T>::put(epoch);
+				Self::deposit_event(Event::EpochFinalized { epoch });
+			} else {
+				progress.last_delegation = page.last().cloned();
+				progress.page += 1;
+				PayoutCursor::<T>::put(progress);
+			}
+			weight.saturating_add(db_weight.writes(2))
+		}
+
+		/// upper bound of the weight of one `do_claim_rewards`, compounding included: the pot, the claim ledger, the
+		/// snapshot, the destination, both balances, the hold and the stake it is added to
+		fn claim_weight() -> Weight {
+			T::DbWeight::get().reads_writes(9, 8)
+		}
+
+		/// function to record the reward pot of each validator at the end of each epoch, rewards are paid out through `claim_rewards`
+		fn distribute_epoch_rewards() {
+			log::debug!("Distribute epoch rewards function called");
 	
-			// Slash the validator's entire stake
-			ValidatorStakes::<T>::mutate(&validator, |stake| {
-				*stake = Zero::zero();
+			let epoch_validators = ActiveValidators::<T>::get(); // the validators that authored the ended epoch
+			let current_epoch = Self::current_epoch();
+			let ended_epoch = current_epoch.saturating_sub(One::one());
+			log::debug!("Current epoch: {:?}, recording rewards for epoch {:?}", current_epoch, ended_epoch);
+
+			// snapshot delegations that can claim for the ended epoch, (validator, amount)
+			let claiming: Vec<(T::AccountId, BalanceOf<T>)> = SnapshotDelegators::<T>::iter_prefix((ended_epoch,))
+				.filter(|(_, delegation)| delegation.epoch_started <= ended_epoch)
+				.map(|((_, validator), delegation)| (validator, delegation.amount))
+				.collect();
+
+			// the epoch payout from the inflation curve is shared between the validators by the blocks they authored,
+			// weighted by the reward curve of their share of the stake
+			let epoch_payout = Self::epoch_payout();
+			let weights = Self::reward_weights(ended_epoch, &epoch_validators, &claiming);
+			let total_weight = weights
+				.iter()
+				.fold(FixedU128::zero(), |total, (_, weight)| total.saturating_add(*weight));
+			log::debug!("Epoch payout of {:?} at a staking ratio of {:?}", epoch_payout, Self::staking_ratio());
+			let mut rewards: Vec<(T::AccountId, BalanceOf<T>)> = Vec::new();
+			let mut requested: BalanceOf<T> = Zero::zero();
+			for (validator, weight) in weights.iter() {
+				let block_count = BlockCount::<T>::get(validator);
+				let total_reward = weight
+					.checked_div(&total_weight)
+					.map(|share| share.saturating_mul_int(epoch_payout))
+					.unwrap_or_else(Zero::zero);
+				log::debug!("Validator {:?} authored {:?} blocks and has a total reward pool of {:?}", validator, block_count, total_reward);
+				requested = requested.saturating_add(total_reward);
+				rewards.push((validator.clone(), total_reward));
+			}
+
+			// the fees `FeeSink` collected for each author are already in the reward pot account. an author that earns
+			// no reward in the epoch has no pot to take its fees, they are withdrawn from the pot and go to `RewardRemainder`
+			let mut fees: BTreeMap<T::AccountId, BalanceOf<T>> = EpochFees::<T>::drain().collect();
+			let mut unrecorded_fees: BalanceOf<T> = Zero::zero();
+			fees.retain(|author, fee| {
+				let recorded = rewards.iter().any(|(validator, _)| validator == author);
+				if !recorded {
+					unrecorded_fees = unrecorded_fees.saturating_add(*fee);
+				}
+				recorded
 			});
+			if !unrecorded_fees.is_zero() {
+				log::debug!("Fees of authors without a reward pot in epoch {:?}: {:?}", ended_epoch, unrecorded_fees);
+				let amount = Self::withdraw_to_remainder(unrecorded_fees);
+				Self::deposit_event(Event::UnrecordedFees { epoch: ended_epoch, amount });
+			}
+			let total_fees = fees.values().fold(BalanceOf::<T>::zero(), |total, fee| total.saturating_add(*fee));
+			if T::RewardFunding::get() == RewardSource::Mint && !total_fees.is_zero() {
+				// the claims mint the fees again
+				let _ = T::NativeBalance::burn_from(&Self::reward_pot_account(), total_fees, Preservation::Expendable, Precision::BestEffort, Fortitude::Polite);
+			}
+
+			// a pot that cannot cover the epoch pays every validator the same fraction of its reward, the fees are always paid in full
+			let mut scale = Perbill::one();
+			if T::RewardFunding::get() == RewardSource::Pot {
+				let available = Self::reward_pot_available().saturating_sub(total_fees);
+				if requested > available {
+					log::debug!("Reward pot depleted in epoch {:?}: requested {:?}, available {:?}", ended_epoch, requested, available);
+					scale = Perbill::from_rational(available, requested);
+					Self::deposit_event(Event::RewardPotDepleted { epoch: ended_epoch, requested, available });
+				}
+			}
+
+			let mut remainder: BalanceOf<T> = Zero::zero();
+			for (validator, total_reward) in rewards.iter() {
+				let total_reward = scale.mul_floor(*total_reward).saturating_add(fees.get(validator).copied().unwrap_or_else(Zero::zero));
+				if total_reward.is_zero() {
+					continue;
+				}
+
+				// Allocate the commission recorded in the epoch snapshot to the validator, not the live value
+				let commission = SnapshotCommissions::<T>::get(ended_epoch, validator)
+					.unwrap_or_else(|| Commissions::<T>::get(validator));
+				let validator_reward = commission.mul_floor(total_reward);
+				let delegators_reward_pool = total_reward.saturating_sub(validator_reward);
+
+				// the pool is split exactly between the snapshot self-stake and the snapshot delegations that earn in the
+				// epoch, stake added mid-epoch is not part of the backing stake. only the rounding dust is left
+				let self_stake = SnapshotSelfStakes::<T>::get(ended_epoch, validator).unwrap_or_else(Zero::zero);
+				let stakes: Vec<BalanceOf<T>> = sp_std::iter::once(self_stake)
+					.chain(claiming.iter().filter(|(delegated_to, _)| delegated_to == validator).map(|(_, amount)| *amount))
+					.collect();
+				let total_stake = stakes.iter().fold(BalanceOf::<T>::zero(), |total, stake| total.saturating_add(*stake));
+				let (shares, dust) = crate::apportion::apportion(delegators_reward_pool, &stakes);
+				log::debug!("Validator {:?} is backed by {:?} of which {:?} self-stake, rounding dust {:?}", validator, total_stake, self_stake, dust);
+				remainder = remainder.saturating_add(dust);
+
+				// record the pot, the validator and the delegators claim their share pro-rata to their stake
+				let claimable = shares.iter().fold(BalanceOf::<T>::zero(), |total, share| total.saturating_add(*share));
+				let unpaid = validator_reward.saturating_add(claimable);
+				EpochRewards::<T>::insert(ended_epoch, validator, RewardPot {
+					validator_reward,
+					delegators_reward: delegators_reward_pool,
+					total_stake,
+					self_stake,
+					unpaid,
+				});
+				CommittedRewards::<T>::mutate(|committed| *committed = committed.saturating_add(unpaid));
 	
-			// Burn the entire held balance
-			T::NativeBalance::burn_held(&HoldReason::Slashing.into(), &validator, validator_stake, Precision::BestEffort, Fortitude::Force)?;
-	
-			// Remove the validator from the PotentialValidators storage
-			PotentialValidators::<T>::remove(&validator);
+				log::debug!("Rewards recorded for validator {:?}: {:?}", validator, total_reward);
+				Self::deposit_event(Event::RewardsRecorded { epoch: ended_epoch, validator: validator.clone(), amount: total_reward });
+			}
+
+			// the rounding dust goes to `RewardRemainder`, minted or withdrawn from the reward pot where it is not committed
+			if !remainder.is_zero() {
+				log::debug!("Unclaimable rewards of epoch {:?}: {:?}", ended_epoch, remainder);
+				let amount = match T::RewardFunding::get() {
+					RewardSource::Mint => {
+						T::RewardRemainder::on_unbalanced(<T::NativeBalance as fungible::Balanced<T::AccountId>>::issue(remainder));
+						remainder
+					},
+					RewardSource::Pot => Self::withdraw_to_remainder(remainder),
+				};
+				Self::deposit_event(Event::UnclaimableRewards { epoch: ended_epoch, amount });
+			}
+
+			Self::prune_expired_rewards(current_epoch);
+		}
+
+		/// function to move up to `amount` out of the reward pot account to `RewardRemainder`, returns the amount moved
+		fn withdraw_to_remainder(amount: BalanceOf<T>) -> BalanceOf<T> {
+			match <T::NativeBalance as fungible::Balanced<T::AccountId>>::withdraw(&Self::reward_pot_account(), amount, Precision::BestEffort, Preservation::Expendable, Fortitude::Polite) {
+				Ok(credit) => {
+					let moved = credit.peek();
+					T::RewardRemainder::on_unbalanced(credit);
+					moved
+				},
+				Err(e) => {
+					log::debug!("Could not withdraw {:?} from the reward pot: {:?}", amount, e);
+					Zero::zero()
+				},
+			}
+		}
+
+		/// function to weigh the blocks each validator authored with the `RewardCurve` multiplier of its share of the
+		/// total stake of `validators` and with its reputation multiplier, validators without blocks are left out. the
+		/// stake is the snapshot self-stake and the `claiming` snapshot delegations of `epoch`, the one its pot is split by
+		fn reward_weights(
+			epoch: BlockNumberFor<T>,
+			validators: &[T::AccountId],
+			claiming: &[(T::AccountId, BalanceOf<T>)],
+		) -> Vec<(T::AccountId, FixedU128)> {
+			let stakes: Vec<BalanceOf<T>> = validators
+				.iter()
+				.map(|validator| {
+					claiming
+						.iter()
+						.filter(|(delegated_to, _)| delegated_to == validator)
+						.fold(SnapshotSelfStakes::<T>::get(epoch, validator).unwrap_or_else(Zero::zero), |stake, (_, amount)| stake.saturating_add(*amount))
+				})
+				.collect();
+			let total_stake = stakes.iter().fold(BalanceOf::<T>::zero(), |total, stake| total.saturating_add(*stake));
+			let shares: Vec<Perbill> = stakes
+				.iter()
+				.map(|stake| if total_stake.is_zero() { Perbill::zero() } else { Perbill::from_rational(*stake, total_stake) })
+				.collect();
+
+			let mut sorted = shares.clone();
+			sorted.sort();
+			let median = sorted.get(sorted.len() / 2).copied().unwrap_or_else(Perbill::zero);
+
+			validators
+				.iter()
+				.zip(shares)
+				.filter_map(|(validator, share)| {
+					let block_count = BlockCount::<T>::get(validator);
+					if block_count == 0 {
+						return None;
+					}
+					let multiplier = T::RewardCurve::multiplier(share, median).saturating_mul(Self::reputation_multiplier(validator));
+					log::debug!("Validator {:?} has a stake share of {:?} and a reward multiplier of {:?}", validator, share, multiplier);
+					Some((validator.clone(), FixedU128::saturating_from_integer(block_count).saturating_mul(multiplier)))
+				})
+				.collect()
+		}
+
+		/// account the rewards are paid from when `RewardFunding` is `RewardSource::Pot`
+		pub fn reward_pot_account() -> T::AccountId {
+			T::PalletId::get().into_account_truncating()
+		}
+
+		/// function to get the part of the reward pot account that is not promised to recorded rewards yet
+		pub fn reward_pot_available() -> BalanceOf<T> {
+			T::NativeBalance::reducible_balance(&Self::reward_pot_account(), Preservation::Preserve, Fortitude::Polite)
+				.saturating_sub(CommittedRewards::<T>::get())
+		}
+
+		/// function to remove reward pots, snapshots and claim ledger entries older than `HistoryDepth` epochs
+		fn prune_expired_rewards(current_epoch: BlockNumberFor<T>) {
+			let history_depth = BlockNumberFor::<T>::from(T::HistoryDepth::get());
+			if current_epoch <= history_depth {
+				return;
+			}
+
+			let expired_epoch = current_epoch - history_depth - One::one();
+			log::debug!("Pruning expired rewards of epoch {:?}", expired_epoch);
+			// rewards nobody claimed in time are no longer promised
+			let unpaid = EpochRewards::<T>::iter_prefix_values(expired_epoch)
+				.fold(BalanceOf::<T>::zero(), |total, pot| total.saturating_add(pot.unpaid));
+			CommittedRewards::<T>::mutate(|committed| *committed = committed.saturating_sub(unpaid));
+			let _ = EpochRewards::<T>::clear_prefix(expired_epoch, u32::MAX, None);
+			let _ = SnapshotDelegators::<T>::clear_prefix((expired_epoch,), u32::MAX, None);
+			let _ = SnapshotCommissions::<T>::clear_prefix(expired_epoch, u32::MAX, None);
+			let _ = SnapshotSelfStakes::<T>::clear_prefix(expired_epoch, u32::MAX, None);
+			let _ = ClaimedRewards::<T>::clear_prefix((expired_epoch,), u32::MAX, None);
+		}
+
+		/// Internal function to slash `fraction` of the stake each account in `exposure` backed the validator with,
+		/// taken from its self-stake or delegation, from stake unbonding from the validator and from stake redelegated
+		/// away from it. returns the slashed funds, the caller hands them to `Slash`
+		fn slash_validator(
+			validator: T::AccountId,
+			fraction: Perbill,
+			exposure: BTreeMap<T::AccountId, BalanceOf<T>>,
+		) -> Result<CreditOf<T>, DispatchError> {
+			// Ensure some stake backed the validator
+			ensure!(!exposure.is_empty(), Error::<T>::ValidatorNotFound);
+			let exposed = exposure.values().fold(BalanceOf::<T>::zero(), |total, amount| total.saturating_add(*amount));
+			ensure!(!exposed.is_zero(), Error::<T>::InsufficientBalance);
+
+			let registered = PotentialValidators::<T>::contains_key(&validator);
+			let mut imbalance = CreditOf::<T>::zero();
+			let mut total_slashed = BalanceOf::<T>::zero();
+			for (who, amount) in exposure {
+				let amount = Self::slash_exposed(&who, &validator, fraction.mul_floor(amount), &mut imbalance);
+				if amount.is_zero() {
+					continue;
+				}
+				total_slashed = total_slashed.saturating_add(amount);
+				Self::deposit_event(Event::Slashed { who, validator: validator.clone(), amount });
+			}
+
+			if registered && PotentialValidators::<T>::get(&validator).is_some_and(|self_stake| self_stake.is_zero()) {
+				// no self-stake left, the validator is unregistered and the delegations left go through the unbonding period
+				log::debug!("Validator {:?} has no self-stake left after the slash, unregistering it", validator);
+				if let Err(e) = Self::do_unregister_validator(validator.clone()) {
+					log::error!("Failed to unregister the slashed validator {:?}: {:?}", validator, e);
+				}
+			}
 	
-			Self::deposit_event(Event::ValidatorSlashed { validator, amount: validator_stake });
-			Ok(())
+			Reputation::<T>::mutate(&validator, |reputation| reputation.slashes = reputation.slashes.saturating_add(1));
+			Self::deposit_event(Event::ValidatorSlashed { validator, fraction, amount: total_slashed });
+			Ok(imbalance)
 		}
 
 	}
@@ -618,8 +2290,10 @@ pub mod pallet {
 	impl<T: Config> Pallet<T> {
 		// A function to get you an account id for the current block author.
 		pub fn find_author() -> Option<T::AccountId> {
-			// If you want to see a realistic example of the `FindAuthor` interface, see `pallet-authorship`.
-			T::FindAuthor::find_author::<'_, Vec<_>>(Default::default())
+			// same as `pallet-authorship`, the author is found in the pre-runtime digests of the block (e.g. the Aura slot)
+			let digest = <frame_system::Pallet<T>>::digest();
+			let pre_runtime_digests = digest.logs.iter().filter_map(|log| log.as_pre_runtime());
+			T::FindAuthor::find_author(pre_runtime_digests)
 		}
 	}
 
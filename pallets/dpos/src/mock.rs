@@ -1,12 +1,15 @@
 use crate::{self as pallet_dpos, ReportNewValidatorSet};
 use frame_support::{
-	derive_impl, parameter_types,
-	traits::{ConstU128, ConstU16, ConstU32, ConstU64, FindAuthor},
+	derive_impl, parameter_types, PalletId,
+	traits::{tokens::imbalance::ResolveTo, ConstU128, ConstU16, ConstU32, ConstU64, FindAuthor},
+	weights::constants::RocksDbWeight,
 };
+use codec::Decode;
 use sp_core::H256;
 use sp_runtime::{
-	traits::{BlakeTwo256, IdentityLookup},
-	BuildStorage,
+	testing::UintAuthorityId,
+	traits::{BlakeTwo256, ConvertInto, IdentityLookup, OpaqueKeys},
+	BuildStorage, KeyTypeId, Perbill, RuntimeAppPublic,
 };
 
 type Block = frame_system::mocking::MockBlock<Test>;
@@ -19,6 +22,7 @@ frame_support::construct_runtime! {
 		System: frame_system,
 		Balances: pallet_balances,
 		Dpos: pallet_dpos,
+		Session: pallet_session,
 	}
 }
 
@@ -29,7 +33,7 @@ impl frame_system::Config for Test {
 	type BaseCallFilter = frame_support::traits::Everything;
 	type BlockWeights = ();
 	type BlockLength = ();
-	type DbWeight = ();
+	type DbWeight = RocksDbWeight;
 	type RuntimeOrigin = RuntimeOrigin;
 	type RuntimeCall = RuntimeCall;
 	type Nonce = u64;
@@ -71,15 +75,45 @@ impl pallet_balances::Config for Test {
 parameter_types! {
 	pub const MaxValidators: u32 = 10;
 	pub static Author: AccountId = 7;
+	pub static AutomaticPayouts: bool = false;
+	// the tests drive `Dpos` alone by default, the session tests run `Session` as well and switch this on
+	pub static SessionRotation: bool = false;
+	pub const MaxCommission: Perbill = Perbill::from_percent(50);
+	// disabled by default, random authors would trip the downtime check in tests that do not expect it
+	pub static OfflineThreshold: Perbill = Perbill::zero();
+	pub const OfflineSlashFraction: Perbill = Perbill::from_percent(10);
+	pub const ReporterReward: Perbill = Perbill::from_percent(10);
+	pub const SlashTreasury: AccountId = 999;
+	pub const RemainderAccount: AccountId = 998;
+	pub const DposPalletId: PalletId = PalletId(*b"dpos/pot");
+	pub static RewardFunding: pallet_dpos::RewardSource = pallet_dpos::RewardSource::Mint;
+	pub const InflationMin: Perbill = Perbill::from_percent(2);
+	pub const InflationMax: Perbill = Perbill::from_percent(10);
+	pub const IdealStakingRatio: Perbill = Perbill::from_percent(50);
+	pub const InflationFalloff: Perbill = Perbill::from_percent(20);
+	// no boost and a zero cap by default, the curve is a no-op until a test sets them
+	pub static CurveBoost: Perbill = Perbill::zero();
+	pub static CurveCap: Perbill = Perbill::zero();
+	// reputation does not change the rewards by default
+	pub static ReputationWeight: Perbill = Perbill::zero();
+	pub const FeeShare: Perbill = Perbill::from_percent(80);
 }
 
+pub const TEST_ENGINE_ID: [u8; 4] = *b"test";
+
 // Custom implementation to find author for DynamicAuthor.
+// the author comes from a `TEST_ENGINE_ID` pre-runtime digest when the block has one, like Aura's slot digest
 pub struct DynamicAuthor;
 impl FindAuthor<AccountId> for DynamicAuthor {
-	fn find_author<'a, I>(_: I) -> Option<AccountId>
+	fn find_author<'a, I>(digests: I) -> Option<AccountId>
 	where
 		I: 'a + IntoIterator<Item = ([u8; 4], &'a [u8])>,
 	{
+		for (id, mut data) in digests.into_iter() {
+			if id == TEST_ENGINE_ID {
+				return AccountId::decode(&mut data).ok();
+			}
+		}
 		Some(Author::get())
 	}
 }
@@ -97,9 +131,66 @@ impl pallet_dpos::Config for Test {
 	type MaxValidators = MaxValidators;
 	type FindAuthor = DynamicAuthor;
 	type ReportNewValidatorSet = DoNothing;
+	type SessionRotation = SessionRotation;
+	type ElectionProvider = pallet_dpos::election::StrategyElection<Test, pallet_dpos::election::TopStake>;
 	// Assuming blocks happen every 6 seconds, this will be 600 seconds, approximately 10 minutes. CONFIGURABLE
 	type EpochDuration = ConstU64<100>;
 	type RuntimeHoldReason = RuntimeHoldReason;
+	type UnbondingEpochs = ConstU32<2>;
+	type MaxUnlockingChunks = ConstU32<8>;
+	type MaxDelegationsPerDelegator = ConstU32<3>;
+	type MinValidatorBond = ConstU128<100>;
+	type MinDelegation = ConstU128<10>;
+	type AdminOrigin = frame_system::EnsureRoot<AccountId>;
+	type RedelegationCooldown = ConstU32<2>;
+	type HistoryDepth = ConstU32<4>;
+	type AutomaticPayouts = AutomaticPayouts;
+	type MaxDelegatorsPerPage = ConstU32<2>;
+	type MaxCommission = MaxCommission;
+	type CommissionChangeDelay = ConstU32<2>;
+	type SlashDeferEpochs = ConstU32<2>;
+	type SlashCancelOrigin = frame_system::EnsureRoot<AccountId>;
+	type OfflineThreshold = OfflineThreshold;
+	type OfflineSlashFraction = OfflineSlashFraction;
+	type ReporterReward = ReporterReward;
+	type MaxReporters = ConstU32<4>;
+	type DisableValidator = ();
+	type Slash = ResolveTo<SlashTreasury, Balances>;
+	type RewardRemainder = ResolveTo<RemainderAccount, Balances>;
+	type PalletId = DposPalletId;
+	type RewardFunding = RewardFunding;
+	type InflationMin = InflationMin;
+	type InflationMax = InflationMax;
+	type IdealStakingRatio = IdealStakingRatio;
+	type InflationFalloff = InflationFalloff;
+	// one epoch a "year" keeps the rewards of the small test issuance well above the rounding dust
+	type EpochsPerYear = ConstU32<1>;
+	type RewardCurve = pallet_dpos::reward_curve::DecentralizationCurve<CurveBoost, CurveCap>;
+	type ReputationTenure = ConstU32<4>;
+	type ReputationWeight = ReputationWeight;
+	type FeeShare = FeeShare;
+}
+
+// session keys of the test validators, the key is the account id
+pub struct TestSessionHandler;
+impl pallet_session::SessionHandler<AccountId> for TestSessionHandler {
+	const KEY_TYPE_IDS: &'static [KeyTypeId] = &[UintAuthorityId::ID];
+	fn on_genesis_session<Ks: OpaqueKeys>(_: &[(AccountId, Ks)]) {}
+	fn on_new_session<Ks: OpaqueKeys>(_: bool, _: &[(AccountId, Ks)], _: &[(AccountId, Ks)]) {}
+	fn on_disabled(_: u32) {}
+}
+
+// one session is one epoch, like in the runtime
+impl pallet_session::Config for Test {
+	type RuntimeEvent = RuntimeEvent;
+	type ValidatorId = AccountId;
+	type ValidatorIdOf = ConvertInto;
+	type ShouldEndSession = pallet_session::PeriodicSessions<ConstU64<100>, ConstU64<0>>;
+	type NextSessionRotation = pallet_session::PeriodicSessions<ConstU64<100>, ConstU64<0>>;
+	type SessionManager = Dpos;
+	type SessionHandler = TestSessionHandler;
+	type Keys = UintAuthorityId;
+	type WeightInfo = ();
 }
 
 // Struct to define initial validators and their balances.
@@ -129,8 +220,8 @@ impl InitialValidators {
 	}
 }
 
-// Function to create externalities for testing.
-pub fn new_test_ext() -> sp_io::TestExternalities {
+// Function to build the genesis storage for testing.
+fn test_storage() -> sp_runtime::Storage {
     // Learn more about improving test setup in the provided link.
 	// https://paritytech.github.io/polkadot-sdk/master/polkadot_sdk_docs/guides/your_first_pallet/index.html
 	// frame_system::GenesisConfig::<Test>::default().build_storage().unwrap().into()
@@ -140,10 +231,32 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
     let genesis_config = pallet_dpos::GenesisConfig::<Test> {
         initial_validators: initial_validators.initial_validators.clone(),
         initial_balances: initial_validators.initial_balances.clone(),
+        initial_commission: Perbill::from_percent(30),
+        reward_pot: 0,
     };
 
-    let mut storage = frame_system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
+    let mut storage = frame_system::GenesisConfig::<Test>::default().build_storage().unwrap();
     genesis_config.assimilate_storage(&mut storage).unwrap();
 
-    storage.into()
+    storage
+}
+
+// Function to create externalities for testing.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	test_storage().into()
+}
+
+// Function to create externalities where `pallet_session` rotates the dpos validators, every genesis validator
+// has its session key
+pub fn new_session_test_ext() -> sp_io::TestExternalities {
+	SessionRotation::set(true);
+	let mut storage = test_storage();
+	let validators = InitialValidators::default().initial_validators;
+	pallet_session::GenesisConfig::<Test> {
+		keys: validators.iter().map(|v| (*v, *v, UintAuthorityId(*v))).collect(),
+	}
+	.assimilate_storage(&mut storage)
+	.unwrap();
+
+	storage.into()
 }
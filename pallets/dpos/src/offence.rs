@@ -0,0 +1,38 @@
+// glue to turn the offences reported through `pallet_offences` (e.g. Aura or BABE equivocations) into slashes
+use crate::pallet::{BalanceOf, Config, Pallet};
+use frame_support::weights::Weight;
+use frame_system::pallet_prelude::BlockNumberFor;
+use sp_runtime::Perbill;
+use sp_staking::{
+	offence::{OffenceDetails, OnOffenceHandler},
+	SessionIndex,
+};
+use sp_std::prelude::*;
+
+/// the offender is the `pallet_session::historical` identification of a validator, its account and its stake.
+/// every offender is disabled for the rest of the epoch and its slash goes through the defense window like any other,
+/// the reporters are paid from the slashed amount once it is applied. one session is one epoch, so the slash hits the
+/// stake that backed the validator in the epoch of `session`, not the stake it has when the report arrives
+impl<T: Config> OnOffenceHandler<T::AccountId, (T::AccountId, BalanceOf<T>), Weight> for Pallet<T> {
+	fn on_offence(
+		offenders: &[OffenceDetails<T::AccountId, (T::AccountId, BalanceOf<T>)>],
+		slash_fraction: &[Perbill],
+		session: SessionIndex,
+	) -> Weight {
+		for (details, fraction) in offenders.iter().zip(slash_fraction) {
+			let (validator, identified_stake) = &details.offender;
+			log::debug!("Offence of validator {:?} with stake {:?} in session {:?}, slash fraction {:?}", validator, identified_stake, session, fraction);
+
+			Self::disable_validator(validator);
+
+			// the fraction is zero for offences that are only worth disabling
+			if fraction.is_zero() {
+				continue;
+			}
+			Self::defer_slash(validator.clone(), *fraction, details.reporters.clone(), BlockNumberFor::<T>::from(session));
+		}
+
+		// We return a default weight because we do not expect you to do weights for your project... Except for extra credit...
+		Weight::default()
+	}
+}
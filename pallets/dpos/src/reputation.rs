@@ -0,0 +1,65 @@
+// reputation of the validators, scored at the end of every epoch from their uptime, their tenure in the
+// validator set and their slash history. it weighs the rewards and breaks election ties
+use crate::pallet::{BalanceOf, BlockCount, Config, Pallet, PotentialValidators, Reputation};
+use frame_support::traits::Get;
+use sp_runtime::{
+	traits::{Saturating, UniqueSaturatedInto},
+	FixedU128, PerThing, Perbill,
+};
+use sp_std::prelude::*;
+
+/// score of a validator: the average of its uptime and its tenure, `tenure` epochs out of `full_tenure`,
+/// divided by one plus the number of slashes it took
+pub fn score(uptime: Perbill, tenure: u32, full_tenure: u32, slashes: u32) -> Perbill {
+	let tenure = if full_tenure == 0 { Perbill::one() } else { Perbill::from_rational(tenure.min(full_tenure), full_tenure) };
+	let half = Perbill::from_percent(50);
+	let clean = (half * uptime).saturating_add(half * tenure);
+	Perbill::from_rational(1, slashes.saturating_add(1)) * clean
+}
+
+impl<T: Config> Pallet<T> {
+	/// function to update the reputation of the validators of the epoch that ended, before the block counts are reset
+	pub(crate) fn update_reputation(validators: &[T::AccountId]) {
+		if validators.is_empty() {
+			return;
+		}
+		let epoch_blocks: u32 = T::EpochDuration::get().unique_saturated_into();
+		let expected = epoch_blocks / validators.len() as u32;
+
+		for validator in validators {
+			let blocks = BlockCount::<T>::get(validator);
+			let epoch_uptime = if expected == 0 { Perbill::one() } else { Perbill::from_rational(blocks, expected) };
+
+			Reputation::<T>::mutate(validator, |reputation| {
+				// the first epoch counts fully, afterwards each epoch weighs as much as all the earlier ones
+				reputation.uptime = if reputation.tenure == 0 {
+					epoch_uptime
+				} else {
+					Perbill::from_parts(reputation.uptime.deconstruct() / 2 + epoch_uptime.deconstruct() / 2)
+				};
+				reputation.tenure = reputation.tenure.saturating_add(1);
+				reputation.score = score(reputation.uptime, reputation.tenure, T::ReputationTenure::get(), reputation.slashes);
+				log::debug!("Validator {:?} authored {:?} of {:?} expected blocks, reputation {:?}", validator, blocks, expected, reputation);
+			});
+		}
+	}
+
+	/// reputation score of a validator, zero for a validator that never was in `ActiveValidators`
+	pub fn reputation_score(validator: &T::AccountId) -> Perbill {
+		Reputation::<T>::get(validator).score
+	}
+
+	/// multiplier of a validator's reward weight, one minus `ReputationWeight` plus `ReputationWeight` of its score
+	pub fn reputation_multiplier(validator: &T::AccountId) -> FixedU128 {
+		let weight = T::ReputationWeight::get();
+		let multiplier = weight.left_from_one().saturating_add(weight * Self::reputation_score(validator));
+		multiplier.into()
+	}
+
+	/// registered validators with their self-stake, the most reputable first so the elections prefer them on equal stake
+	pub fn candidates_by_reputation() -> Vec<(T::AccountId, BalanceOf<T>)> {
+		let mut candidates: Vec<(T::AccountId, BalanceOf<T>)> = PotentialValidators::<T>::iter().collect();
+		candidates.sort_by_key(|(validator, _)| core::cmp::Reverse(Self::reputation_score(validator)));
+		candidates
+	}
+}
@@ -0,0 +1,53 @@
+// reward curves that reshape how the epoch payout is shared between the validators, to push stake away from
+// the biggest validators towards the smaller ones
+use frame_support::traits::Get;
+use sp_runtime::{
+	traits::{CheckedDiv, One, Saturating, Zero},
+	FixedPointNumber, FixedU128, Perbill,
+};
+use sp_std::marker::PhantomData;
+
+/// trait to weigh a validator's share of the epoch payout by its share of the total stake of the epoch's validators.
+/// the payout stays the same, a multiplier above one takes a bigger slice of it from the other validators
+pub trait RewardCurve {
+	/// `share` is the validator's share of the total stake, `median` the median share of the epoch's validators
+	fn multiplier(share: Perbill, median: Perbill) -> FixedU128;
+}
+
+/// linear no-op, every authored block is worth the same
+impl RewardCurve for () {
+	fn multiplier(_share: Perbill, _median: Perbill) -> FixedU128 {
+		FixedU128::one()
+	}
+}
+
+/// boosts validators below the median share by up to `Boost` (at no stake at all). from the median on the multiplier
+/// follows the stake backing the validator up to `Cap`, every staked token earns the same, and saturates above it: the
+/// share above the cap counts for progressively less, `cap * (2 - cap / share)` in total, so a validator never earns
+/// less for growing but can not earn more than twice the stake of the cap. a zero cap flattens it at one
+pub struct DecentralizationCurve<Boost, Cap>(PhantomData<(Boost, Cap)>);
+
+impl<Boost: Get<Perbill>, Cap: Get<Perbill>> DecentralizationCurve<Boost, Cap> {
+	/// the share that earns in full, the share below the cap and the diminishing part of the share above it
+	fn effective_share(share: Perbill) -> FixedU128 {
+		let cap = Cap::get();
+		if share <= cap {
+			return share.into();
+		}
+		let cap: FixedU128 = cap.into();
+		let above = cap.saturating_mul(cap).checked_div(&share.into()).unwrap_or_else(FixedU128::zero);
+		cap.saturating_mul(FixedU128::saturating_from_integer(2)).saturating_sub(above)
+	}
+}
+
+impl<Boost: Get<Perbill>, Cap: Get<Perbill>> RewardCurve for DecentralizationCurve<Boost, Cap> {
+	fn multiplier(share: Perbill, median: Perbill) -> FixedU128 {
+		if share < median {
+			let below = Perbill::from_rational(median.saturating_sub(share).deconstruct(), median.deconstruct());
+			return FixedU128::one().saturating_add((Boost::get() * below).into());
+		}
+		Self::effective_share(share)
+			.checked_div(&Self::effective_share(median))
+			.unwrap_or_else(FixedU128::one)
+	}
+}
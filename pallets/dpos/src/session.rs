@@ -0,0 +1,70 @@
+// glue to let `pallet_session` drive the validator rotation, one session is one dpos epoch when the runtime
+// uses `EpochDuration` as the session period
+use crate::pallet::{BalanceOf, Config, CurrentValidators, Pallet, QueuedValidators, ValidatorStakes};
+use pallet_session::{historical, SessionManager};
+use sp_runtime::traits::Convert;
+use sp_staking::SessionIndex;
+use sp_std::{marker::PhantomData, prelude::*};
+
+/// `pallet_session` queues the set `new_session` hands out and only activates it when the next session starts, so the
+/// set elected at an epoch boundary authors the epoch after. the pallet has to come before `pallet_session` in the
+/// runtime, so the ended epoch is accounted against the set that authored it before `start_session` promotes the
+/// queued set to `ActiveValidators`. the runtime sets `SessionRotation`
+impl<T: Config> SessionManager<T::AccountId> for Pallet<T> {
+	fn new_session(new_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+		let validators = CurrentValidators::<T>::get();
+		log::debug!("New session {:?} planned for epoch {:?} with validators {:?}", new_index, Self::current_epoch(), validators);
+
+		// never hand an empty set to the session pallet, it keeps the previous one instead
+		if validators.is_empty() {
+			return None;
+		}
+		QueuedValidators::<T>::insert(new_index, validators.clone());
+		Some(validators.into())
+	}
+
+	fn end_session(end_index: SessionIndex) {
+		log::debug!("Session {:?} ended", end_index);
+	}
+
+	fn start_session(start_index: SessionIndex) {
+		log::debug!("Session {:?} started", start_index);
+		// no set queued for the session means the session pallet kept the active one
+		if let Some(validators) = QueuedValidators::<T>::take(start_index) {
+			Self::activate_validators(validators);
+		}
+	}
+}
+
+/// the full identification of a validator is its total stake, kept by `pallet_session::historical` so offences
+/// reported for past sessions can still be checked against the stake the validator had back then
+impl<T: Config> historical::SessionManager<T::AccountId, BalanceOf<T>> for Pallet<T> {
+	fn new_session(new_index: SessionIndex) -> Option<Vec<(T::AccountId, BalanceOf<T>)>> {
+		<Self as SessionManager<_>>::new_session(new_index).map(|validators| {
+			validators
+				.into_iter()
+				.map(|validator| {
+					let stake = ValidatorStakes::<T>::get(&validator);
+					(validator, stake)
+				})
+				.collect()
+		})
+	}
+
+	fn start_session(start_index: SessionIndex) {
+		<Self as SessionManager<_>>::start_session(start_index)
+	}
+
+	fn end_session(end_index: SessionIndex) {
+		<Self as SessionManager<_>>::end_session(end_index)
+	}
+}
+
+/// `FullIdentificationOf` for `pallet_session::historical`, the current total stake of a validator
+pub struct StakeOf<T>(PhantomData<T>);
+
+impl<T: Config> Convert<T::AccountId, Option<BalanceOf<T>>> for StakeOf<T> {
+	fn convert(validator: T::AccountId) -> Option<BalanceOf<T>> {
+		ValidatorStakes::<T>::contains_key(&validator).then(|| ValidatorStakes::<T>::get(&validator))
+	}
+}
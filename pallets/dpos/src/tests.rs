@@ -1,11 +1,18 @@
+
 use crate::{mock::*, *};
-use frame_support::{assert_noop, assert_ok, traits::{Currency, OnInitialize, OnFinalize}};
+use frame_support::{assert_noop, assert_ok, traits::{Currency, Get, OnInitialize, OnFinalize, OnUnbalanced}};
 use frame_system::pallet_prelude::BlockNumberFor;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use rand::Rng;
-use env_logger;
-use sp_runtime::traits::Zero;
+use sp_runtime::{traits::{Convert, Zero}, Perbill};
+use frame_election_provider_support::{
+    bounds::{DataProviderBounds, ElectionBounds, ElectionBoundsBuilder},
+    onchain, ElectionDataProvider, ElectionProvider, SequentialPhragmen,
+};
+use frame_support::{parameter_types, traits::tokens::imbalance::ResolveTo};
+use proptest::prelude::*;
+use sp_runtime::testing::UintAuthorityId;
 
 
 // function to advance blocks so you can run on_initialize logic or something that uses block numbers
@@ -36,6 +43,47 @@ pub fn run_to_block(n: BlockNumberFor<Test>) {
     }
 }
 
+// function to advance blocks with the authors picked round-robin from the current validators that are not `offline`,
+// so the offline validators miss all their slots
+pub fn run_to_block_offline(n: BlockNumberFor<Test>, offline: &[u64]) {
+    while System::block_number() < n {
+        if System::block_number() > 1 {
+            Dpos::on_finalize(System::block_number());
+            System::on_finalize(System::block_number());
+        }
+        next_block();
+        let online: Vec<u64> = CurrentValidators::<Test>::get()
+            .into_iter()
+            .filter(|validator| !offline.contains(validator))
+            .collect();
+        if !online.is_empty() {
+            mock::Author::set(online[System::block_number() as usize % online.len()]);
+        }
+    }
+}
+
+// function to advance blocks with `pallet_session` rotating the validators after `Dpos`, like in the runtime. the
+// authors are picked round-robin from the session's validators, the set that is really active
+pub fn run_to_block_with_session(n: BlockNumberFor<Test>) {
+    while System::block_number() < n {
+        if System::block_number() > 1 {
+            Dpos::on_finalize(System::block_number());
+            System::on_finalize(System::block_number());
+        }
+        System::set_block_number(System::block_number() + 1);
+        System::on_initialize(System::block_number());
+        Dpos::on_initialize(System::block_number());
+        Session::on_initialize(System::block_number());
+        let validators = pallet_session::Validators::<Test>::get();
+        mock::Author::set(validators[System::block_number() as usize % validators.len()]);
+    }
+}
+
+// helper function to get what a validator claims from its pot, the commission plus the share of its self-stake
+fn validator_claim(pot: &RewardPot<Test>) -> u128 {
+    pot.validator_reward + crate::apportion::share(pot.delegators_reward, pot.self_stake, pot.total_stake)
+}
+
 // helper function to print the total delegated stake for each elected validator
 fn print_total_delegated_stake_for_elected<T: Config>() {
     log::info!("Total delegated stake for each elected validator:");
@@ -58,11 +106,11 @@ fn it_works_for_default_value() {
 		Balances::make_free_balance_be(&55, 1_000);
 
 		// Test registering a validator
-		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
 		// Verify storage updates
-		assert!(PotentialValidators::<Test>::contains_key(&55));
-		assert_eq!(PotentialValidators::<Test>::get(&55), Some(500));
+		assert!(PotentialValidators::<Test>::contains_key(55));
+		assert_eq!(PotentialValidators::<Test>::get(55), Some(500));
 
 		// Verify event emission
 		System::assert_last_event(Event::ValidatorRegistered { validator: 55, amount: 500 }.into());
@@ -82,11 +130,11 @@ fn register_validator() {
         Balances::make_free_balance_be(&55, 1_000);
 
         // Test registering a validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Verify storage updates
-        assert!(PotentialValidators::<Test>::contains_key(&55));
-        assert_eq!(PotentialValidators::<Test>::get(&55), Some(500));
+        assert!(PotentialValidators::<Test>::contains_key(55));
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(500));
 
         // Verify event emission
         System::assert_last_event(Event::ValidatorRegistered { validator: 55, amount: 500 }.into());
@@ -106,13 +154,13 @@ fn register_multiple_validators() {
 
         // Register validators
         for i in 11..=15 {
-            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(i), 500));
+            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(i), 500, Perbill::from_percent(30)));
         }
 
         // Verify storage updates
         for i in 11..=15 {
-            assert!(PotentialValidators::<Test>::contains_key(&i));
-            assert_eq!(PotentialValidators::<Test>::get(&i), Some(500));
+            assert!(PotentialValidators::<Test>::contains_key(i));
+            assert_eq!(PotentialValidators::<Test>::get(i), Some(500));
         }
 
         // Verify event emission
@@ -130,7 +178,7 @@ fn register_validator_with_insufficient_balance() {
 
 		// Test registering a validator with insufficient balance
 		assert_noop!(
-			Dpos::register_validator(RuntimeOrigin::signed(55), 500),
+			Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)),
 			Error::<Test>::InsufficientBalance
 		);
 	});
@@ -144,17 +192,127 @@ fn register_validator_twice() {
 
         Balances::make_free_balance_be(&55, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Test registering a validator twice
         assert_noop!(
-            Dpos::register_validator(RuntimeOrigin::signed(55), 500),
+            Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)),
             Error::<Test>::AlreadyRegistered
         );
     });
 }
 
 
+// test validator registering with a commission above the runtime bound
+#[test]
+fn register_validator_commission_too_high() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+
+        assert_noop!(
+            Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(51)),
+            Error::<Test>::CommissionTooHigh
+        );
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(50)));
+        assert_eq!(Commissions::<Test>::get(55), Perbill::from_percent(50));
+    });
+}
+
+// ----- commission tests -------
+
+// lowering the commission applies right away
+#[test]
+fn set_commission_decrease() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+
+        assert_ok!(Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(10)));
+        assert_eq!(Commissions::<Test>::get(55), Perbill::from_percent(10));
+        System::assert_last_event(Event::CommissionSet { validator: 55, commission: Perbill::from_percent(10) }.into());
+    });
+}
+
+// raising the commission only applies after the delay
+#[test]
+fn set_commission_increase_is_delayed() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+
+        assert_ok!(Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(40)));
+        System::assert_last_event(Event::CommissionChangeScheduled {
+            validator: 55,
+            commission: Perbill::from_percent(40),
+            effective_epoch: 2,
+        }.into());
+        assert_eq!(Commissions::<Test>::get(55), Perbill::from_percent(10));
+
+        run_to_block(199);
+        assert_eq!(Commissions::<Test>::get(55), Perbill::from_percent(10));
+
+        run_to_block(200);
+        assert_eq!(Commissions::<Test>::get(55), Perbill::from_percent(40));
+        assert!(!PendingCommissions::<Test>::contains_key(55));
+        assert_eq!(SnapshotCommissions::<Test>::get(2, 55), Some(Perbill::from_percent(40)));
+    });
+}
+
+// set_commission checks the caller and the bound
+#[test]
+fn set_commission_invalid() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+
+        assert_noop!(
+            Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(10)),
+            Error::<Test>::ValidatorNotFound
+        );
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+        assert_noop!(
+            Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(60)),
+            Error::<Test>::CommissionTooHigh
+        );
+    });
+}
+
+// the validator's cut uses the commission of the epoch snapshot, not the live value
+#[test]
+fn rewards_use_snapshot_commission() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(20)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        run_to_block(150);
+        assert_eq!(SnapshotCommissions::<Test>::get(1, 55), Some(Perbill::from_percent(20)));
+
+        // lower the commission mid-epoch
+        assert_ok!(Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(5)));
+
+        run_to_block(200);
+        let pot = EpochRewards::<Test>::get(1, 55).unwrap();
+        let total = pot.validator_reward + pot.delegators_reward;
+        assert_eq!(pot.validator_reward, Perbill::from_percent(20).mul_floor(total));
+
+        // the next epoch uses the new commission
+        assert_eq!(SnapshotCommissions::<Test>::get(2, 55), Some(Perbill::from_percent(5)));
+    });
+}
+
 // ----- unregister validator tests -------
 
 // test for unregistering a validator
@@ -170,7 +328,7 @@ fn unregister_validator() {
         Balances::make_free_balance_be(&77, 1_000);
 
         // Register validator and delegate
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 200));
 
@@ -178,10 +336,10 @@ fn unregister_validator() {
         assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
         // Verify storage updates
-        assert!(!PotentialValidators::<Test>::contains_key(&55));
-        assert!(!ValidatorStakes::<Test>::contains_key(&55));
-        assert!(!Delegators::<Test>::contains_key(&66));
-        assert!(!Delegators::<Test>::contains_key(&77));
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert!(!ValidatorStakes::<Test>::contains_key(55));
+        assert!(!Delegators::<Test>::contains_key(66, 55));
+        assert!(!Delegators::<Test>::contains_key(77, 55));
 
         // Verify event emission
         System::assert_last_event(Event::ValidatorDeregistered {
@@ -218,14 +376,14 @@ fn unregister_validator_with_no_delegators() {
         Balances::make_free_balance_be(&55, 1_000);
 
         // Register validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Attempt to unregister a validator with no delegators
         assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
         // Verify storage updates
-        assert!(!PotentialValidators::<Test>::contains_key(&55));
-        assert!(!ValidatorStakes::<Test>::contains_key(&55));
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert!(!ValidatorStakes::<Test>::contains_key(55));
     });
 }
 
@@ -239,14 +397,93 @@ fn register_and_deregister_validator() {
         Balances::make_free_balance_be(&55, 1_000);
 
         // Register validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Deregister the validator
         assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
         // Verify storage updates
-        assert!(!PotentialValidators::<Test>::contains_key(&55));
-        assert!(!ValidatorStakes::<Test>::contains_key(&55));
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert!(!ValidatorStakes::<Test>::contains_key(55));
+    });
+}
+
+// ------ self-stake tests -------
+
+// test adding self-stake after registration
+#[test]
+fn bond_extra_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        assert_ok!(Dpos::bond_extra(RuntimeOrigin::signed(55), 200));
+
+        // self-stake, total stake and hold move together
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(700));
+        assert_eq!(ValidatorStakes::<Test>::get(55), 1_000);
+        assert_eq!(Balances::free_balance(55), 300);
+        System::assert_last_event(Event::BondedExtra { validator: 55, amount: 200 }.into());
+
+        assert_noop!(Dpos::bond_extra(RuntimeOrigin::signed(55), 400), Error::<Test>::InsufficientBalance);
+        assert_noop!(Dpos::bond_extra(RuntimeOrigin::signed(55), 0), Error::<Test>::InvalidAmount);
+        assert_noop!(Dpos::bond_extra(RuntimeOrigin::signed(66), 100), Error::<Test>::ValidatorNotFound);
+    });
+}
+
+// test unbonding part of the self-stake goes through the unbonding period
+#[test]
+fn unbond_self_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        assert_ok!(Dpos::unbond_self(RuntimeOrigin::signed(55), 300));
+
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(200));
+        assert_eq!(ValidatorStakes::<Test>::get(55), 500);
+        // the delegation is not touched
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 300);
+
+        // the amount stays held until the unbonding period is over
+        let chunks = Unlocking::<Test>::get(55);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].amount, 300);
+        assert_eq!(chunks[0].reason, HoldReason::ValidatorRegistration);
+        assert_eq!(Balances::free_balance(55), 500);
+
+        run_to_block(200);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(55)));
+        System::assert_last_event(Event::Withdrawn { who: 55, amount: 300 }.into());
+    });
+}
+
+// test unbonding self-stake below the minimum bond
+#[test]
+fn unbond_self_below_minimum() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+
+        // MinValidatorBond is 100 in the mock
+        assert_noop!(Dpos::unbond_self(RuntimeOrigin::signed(55), 401), Error::<Test>::ValidatorBondTooLow);
+        assert_noop!(Dpos::unbond_self(RuntimeOrigin::signed(55), 600), Error::<Test>::InsufficientBalance);
+        assert_noop!(Dpos::unbond_self(RuntimeOrigin::signed(66), 100), Error::<Test>::ValidatorNotFound);
+        assert_ok!(Dpos::unbond_self(RuntimeOrigin::signed(55), 400));
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(100));
     });
 }
 
@@ -261,13 +498,13 @@ fn delegate_to_validator() {
 		Balances::make_free_balance_be(&55, 1_000);
 		Balances::make_free_balance_be(&66, 1_000);
 
-		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
 		assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
 		// Verify storage updates
-		assert!(Delegators::<Test>::contains_key(&66));
-		let delegation = Delegators::<Test>::get(&66).unwrap();
+		assert!(Delegators::<Test>::contains_key(66, 55));
+		let delegation = Delegators::<Test>::get(66, 55).unwrap();
 		assert_eq!(delegation.validator, 55);
 		assert_eq!(delegation.amount, 300);
 
@@ -285,7 +522,7 @@ fn delegate_to_unregistered_validator() {
 		Balances::make_free_balance_be(&55, 1_000);
 		Balances::make_free_balance_be(&66, 1_000);
 
-		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
 		// Test delegating to an unregistered validator
 		assert_noop!(
@@ -304,7 +541,7 @@ fn delegate_more_than_balance() {
 		Balances::make_free_balance_be(&55, 1_000);
 		Balances::make_free_balance_be(&66, 1_000);
 
-		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+		assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
 		// Test delegating more than the delegator's balance
 		assert_noop!(
@@ -314,7 +551,7 @@ fn delegate_more_than_balance() {
 	});
 }
 
-// test delegator trying to delegate to more than one validator
+// test delegator delegating to more than one validator
 #[test]
 fn delegate_to_multiple_validators() {
     new_test_ext().execute_with(|| {
@@ -324,26 +561,88 @@ fn delegate_to_multiple_validators() {
         Balances::make_free_balance_be(&66, 1_000);
         Balances::make_free_balance_be(&77, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(66), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(66), 500, Perbill::from_percent(30)));
 
         // Delegate to a validator
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 300));
 
-        // Attempt to delegate to another validator should fail
-        assert_noop!(
-            Dpos::delegate(RuntimeOrigin::signed(77), 66, 300),
-            Error::<Test>::AlreadyDelegated
-        );
+        // Delegate to another validator from the same account
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 66, 200));
 
-        // Verify storage updates
-        assert!(Delegators::<Test>::contains_key(&77));
-        let delegation = Delegators::<Test>::get(&77).unwrap();
+        // Verify storage updates, each delegation is stored separately
+        let delegation = Delegators::<Test>::get(77, 55).unwrap();
         assert_eq!(delegation.validator, 55);
         assert_eq!(delegation.amount, 300);
+        let delegation = Delegators::<Test>::get(77, 66).unwrap();
+        assert_eq!(delegation.validator, 66);
+        assert_eq!(delegation.amount, 200);
+        assert_eq!(ValidatorStakes::<Test>::get(55), 800);
+        assert_eq!(ValidatorStakes::<Test>::get(66), 700);
 
         // Verify event emission
-        System::assert_last_event(Event::Delegated { delegator: 77, validator: 55, amount: 300 }.into());
+        System::assert_last_event(Event::Delegated { delegator: 77, validator: 66, amount: 200 }.into());
+
+        // undelegating from one validator leaves the other delegation untouched
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(77), 55, 300));
+        assert!(!Delegators::<Test>::contains_key(77, 55));
+        assert_eq!(Delegators::<Test>::get(77, 66).unwrap().amount, 200);
+        assert_eq!(ValidatorStakes::<Test>::get(66), 700);
+    });
+}
+
+// test delegator trying to back more validators than allowed
+#[test]
+fn delegate_to_too_many_validators() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&77, 10_000);
+
+        // MaxDelegationsPerDelegator is 3 in the mock
+        for validator in 51..=54 {
+            Balances::make_free_balance_be(&validator, 1_000);
+            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(validator), 500, Perbill::from_percent(30)));
+        }
+        for validator in 51..=53 {
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), validator, 100));
+        }
+
+        assert_noop!(
+            Dpos::delegate(RuntimeOrigin::signed(77), 54, 100),
+            Error::<Test>::TooManyDelegations
+        );
+
+        // adding to an existing delegation is still possible
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 53, 100));
+        assert_eq!(Delegators::<Test>::get(77, 53).unwrap().amount, 200);
+    });
+}
+
+// each delegation earns its own share of the validator's rewards
+#[test]
+fn rewards_for_each_delegation() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(66), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 300));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 66, 200));
+
+        run_to_block(200);
+
+        // both delegations were in the snapshot of epoch 1 and are claimed separately
+        assert!(SnapshotDelegators::<Test>::contains_key((1, 77, 55)));
+        assert!(SnapshotDelegators::<Test>::contains_key((1, 77, 66)));
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 55));
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 66));
+        assert!(ClaimedRewards::<Test>::get((1, 55, 77)));
+        assert!(ClaimedRewards::<Test>::get((1, 66, 77)));
     });
 }
 
@@ -356,13 +655,13 @@ fn delegate_storage_update() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Verify storage updates
-        assert!(Delegators::<Test>::contains_key(&66));
-        let delegation = Delegators::<Test>::get(&66).unwrap();
+        assert!(Delegators::<Test>::contains_key(66, 55));
+        let delegation = Delegators::<Test>::get(66, 55).unwrap();
         assert_eq!(delegation.validator, 55);
         assert_eq!(delegation.amount, 300);
     });
@@ -377,7 +676,7 @@ fn delegate_zero_balance() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Test delegating zero balance
         assert_noop!(
@@ -398,7 +697,7 @@ fn delegate_after_unregistering_validator() {
         Balances::make_free_balance_be(&66, 1_000);
 
         // Register and then unregister a validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
         // Attempt to delegate to an unregistered validator
@@ -423,14 +722,14 @@ fn delegate_with_exact_balance() {
         Balances::make_free_balance_be(&55, balance);
         Balances::make_free_balance_be(&66, balance);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Test delegating the exact available balance minus the fee
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, delegation_amount));
 
         // Verify storage updates
-        assert!(Delegators::<Test>::contains_key(&66));
-        let delegation = Delegators::<Test>::get(&66).unwrap();
+        assert!(Delegators::<Test>::contains_key(66, 55));
+        let delegation = Delegators::<Test>::get(66, 55).unwrap();
         assert_eq!(delegation.validator, 55);
         assert_eq!(delegation.amount, delegation_amount);
 
@@ -452,15 +751,15 @@ fn partial_undelegate() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Test partial undelegation
-        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 100));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 100));
 
         // Verify storage updates
-        assert!(Delegators::<Test>::contains_key(&66));
-        let delegation = Delegators::<Test>::get(&66).unwrap();
+        assert!(Delegators::<Test>::contains_key(66, 55));
+        let delegation = Delegators::<Test>::get(66, 55).unwrap();
         assert_eq!(delegation.validator, 55);
         assert_eq!(delegation.amount, 200);
 
@@ -480,11 +779,11 @@ fn undelegation_no_delegation_found() {
         Balances::make_free_balance_be(&66, 1_000);
 
         // Register validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
         // Attempt to undelegate without delegation
         assert_noop!(
-            Dpos::undelegate(RuntimeOrigin::signed(66), 200),
+            Dpos::undelegate(RuntimeOrigin::signed(66), 55, 200),
             Error::<Test>::NoDelegationFound
         );
     });
@@ -501,12 +800,12 @@ fn undelegation_insufficient_balance() {
         Balances::make_free_balance_be(&66, 1_000);
 
         // Register validator and delegate
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Attempt to undelegate more than delegated amount
         assert_noop!(
-            Dpos::undelegate(RuntimeOrigin::signed(66), 400),
+            Dpos::undelegate(RuntimeOrigin::signed(66), 55, 400),
             Error::<Test>::InsufficientBalance
         );
     });
@@ -522,16 +821,16 @@ fn complete_undelegation() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
         
         // Test complete undelegation
-        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Verify storage updates
-        assert!(!Delegators::<Test>::contains_key(&66));
+        assert!(!Delegators::<Test>::contains_key(66, 55));
 
-        let validator_stake = ValidatorStakes::<Test>::get(&55);
+        let validator_stake = ValidatorStakes::<Test>::get(55);
         assert_eq!(validator_stake, 500);
 
         // Verify event emission
@@ -554,18 +853,18 @@ fn redelegate_after_undelegation() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Test undelegation
-        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Test re-delegation
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Verify storage updates
-        assert!(Delegators::<Test>::contains_key(&66));
-        let delegation = Delegators::<Test>::get(&66).unwrap();
+        assert!(Delegators::<Test>::contains_key(66, 55));
+        let delegation = Delegators::<Test>::get(66, 55).unwrap();
         assert_eq!(delegation.validator, 55);
         assert_eq!(delegation.amount, 300);
 
@@ -576,239 +875,2196 @@ fn redelegate_after_undelegation() {
 
 
 
-// ------ update validator set tests -------
-
-// test for validator set update based on total stake
-// add 10 validators and for each validator 3 delegators,select the top 5 validators 
-// then stake more and update the validator set again and check if the top 5 validators upd
-// added dynamic testing and removed hardcoding - tested for a large number of validators
-// prints the logs from the functions in order to check that everything is working as expected
-// took out the assert_eq! for the validators as it was failing due to the randomness of the stake
+// ------- minimum amount tests -------
 
+// test registering with less than the minimum self-bond
 #[test]
-fn update_validator_set() {
+fn register_validator_bond_too_low() {
     new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-		let _ = env_logger::builder().is_test(true).try_init();
-
-        run_to_block(99);
-
-        let pool: u64 = 20;
-
-        // Fund accounts with sufficient balance
-        for i in 11..=pool {
-            Balances::make_free_balance_be(&i, 1_000);
-        }
-
-        // Register validators
-        for i in 11..=pool {
-            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(i), 100));
-        }
+        Balances::make_free_balance_be(&55, 1_000);
 
-        // Delegate to validators
-        for i in 11..=pool {
-            for j in 11..=15 {
-                let num = rand::thread_rng().gen_range(9..50); // Generate random number in the range
-                let delegator = j * pool + i; // create unique delegator ids
-                Balances::make_free_balance_be(&delegator, 10_000);
-                let amount = (i * num).into(); // delegate different amounts based on `i`
-                assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), i, amount));
-            }
-        }
+        // MinValidatorBond is 100 in the mock
+        assert_noop!(
+            Dpos::register_validator(RuntimeOrigin::signed(55), 99, Perbill::from_percent(30)),
+            Error::<Test>::ValidatorBondTooLow
+        );
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 100, Perbill::from_percent(30)));
+    });
+}
 
-        // Advance to the next block to trigger validator update
-        run_to_block(100);
+// test delegating less than the minimum delegation
+#[test]
+fn delegate_below_minimum() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-        // Verify storage updates
-        let current_validators = CurrentValidators::<Test>::get();
-        // log::info!("Current validators: {:?}", current_validators);
-        let max_validators = <Test as crate::Config>::MaxValidators::get() as usize;
-        assert_eq!(current_validators.len(), max_validators);
-		//let expected_validators: Vec<u64> = (6..=15).rev().collect();
-        let max_validators_u64 = max_validators as u64;
-        //let expected_validators: Vec<u64> = ((pool - max_validators_u64 + 1)..=pool).rev().collect();
-        //assert_eq!(current_validators.into_inner(), expected_validators);
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
 
-        // Verify event emission
-		let events = System::events();
-        assert!(events.iter().any(|record| {
-            matches!(record.event, RuntimeEvent::Dpos(Event::ValidatorsUpdated))
-        }));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
 
-		// Print total stake for each validator
-        print_total_delegated_stake_for_elected::<Test>();
+        // MinDelegation is 10 in the mock
+        assert_noop!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 9), Error::<Test>::DelegationTooLow);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 10));
 
-		let poor: u64 = (pool - max_validators_u64)/2;
-        // Second round: Add more stake to the lower ranked validators
-        for i in 11..=poor {
-            for j in 11..=13 {
-                let num = rand::thread_rng().gen_range(9..50); // Generate random number in the range 
-                let delegator = j * pool + i; // same delegators
-                assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), i, (i * num).into()));
-            }
-        }
+        // topping up an existing delegation only needs the total to reach the minimum
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 1));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 11);
+    });
+}
 
-		// advance to the next block to trigger validator update
-		run_to_block(200);
+// test a partial undelegation leaving less than the minimum behind
+#[test]
+fn undelegate_remainder_below_minimum() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-		// Verify storage updates
-		let current_validators = CurrentValidators::<Test>::get();
-		// log::info!("Current validators: {:?}", current_validators);
-		assert_eq!(current_validators.len(), max_validators);
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
 
-		// Verify event emission
-		let events = System::events();
-        assert!(events.iter().any(|record| {
-            matches!(record.event, RuntimeEvent::Dpos(Event::ValidatorsUpdated))
-        }));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 100));
 
-        print_total_delegated_stake_for_elected::<Test>();
+        assert_noop!(
+            Dpos::undelegate(RuntimeOrigin::signed(66), 55, 95),
+            Error::<Test>::DelegationRemainderTooLow
+        );
+        assert_noop!(
+            Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 95),
+            Error::<Test>::DelegationRemainderTooLow
+        );
+        assert_noop!(
+            Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 5),
+            Error::<Test>::DelegationTooLow
+        );
 
+        // leaving exactly the minimum or undelegating everything is fine
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 90));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 10));
+        assert!(!Delegators::<Test>::contains_key(66, 55));
     });
 }
 
-// test fair epoch reward distribution for validators and delegators
+// test governance changing the minimums at runtime
 #[test]
-fn delegators_receive_rewards_only_after_next_epoch() {
+fn set_minimums_works() {
     new_test_ext().execute_with(|| {
-        let _ = env_logger::builder().is_test(true).try_init();
         run_to_block(1);
 
-        Balances::make_free_balance_be(&55, 1_000); // Validator
-        Balances::make_free_balance_be(&66, 1_000); // Delegator 1
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        // only the admin origin can change the minimums
+        assert_noop!(
+            Dpos::set_minimums(RuntimeOrigin::signed(55), Some(600), None),
+            sp_runtime::DispatchError::BadOrigin
+        );
+
+        assert_ok!(Dpos::set_minimums(RuntimeOrigin::root(), Some(600), Some(50)));
+        assert_eq!(MinimumValidatorBond::<Test>::get(), 600);
+        assert_eq!(MinimumDelegation::<Test>::get(), 50);
+        System::assert_last_event(Event::MinimumsSet { min_validator_bond: 600, min_delegation: 50 }.into());
+
+        assert_noop!(
+            Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)),
+            Error::<Test>::ValidatorBondTooLow
+        );
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 600, Perbill::from_percent(30)));
+        assert_noop!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 49), Error::<Test>::DelegationTooLow);
+
+        // `None` keeps the current value
+        assert_ok!(Dpos::set_minimums(RuntimeOrigin::root(), None, Some(10)));
+        assert_eq!(MinimumValidatorBond::<Test>::get(), 600);
+        assert_eq!(MinimumDelegation::<Test>::get(), 10);
+    });
+}
+
+// ------- redelegation tests -------
+
+// test moving part of a delegation to a current validator
+#[test]
+fn redelegate_moves_stake() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        let stake_of_1 = ValidatorStakes::<Test>::get(1);
+
+        assert_ok!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 200));
+
+        // both delegations and stakes are updated
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 100);
+        let delegation = Delegators::<Test>::get(66, 1).unwrap();
+        assert_eq!(delegation.amount, 200);
+        // validator 1 is a current validator, so the new delegation starts in the next epoch
+        assert_eq!(delegation.epoch_started, 1);
+        assert_eq!(ValidatorStakes::<Test>::get(55), 600);
+        assert_eq!(ValidatorStakes::<Test>::get(1), stake_of_1 + 200);
+
+        // the hold stays in place and nothing is unbonding
+        assert_eq!(Balances::free_balance(66), 700);
+        assert!(Unlocking::<Test>::get(66).is_empty());
+
+        System::assert_last_event(Event::Redelegated { delegator: 66, from: 55, to: 1, amount: 200 }.into());
+    });
+}
+
+// test stake redelegated away from a validator stays slashable for an offence reported afterwards
+#[test]
+fn redelegated_stake_slashed_for_later_offence() {
+    use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        let stake_of_1 = ValidatorStakes::<Test>::get(1);
+
+        // the delegator moves everything away before the offence is known
+        assert_ok!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 300));
+        let liability = &RedelegationLiabilities::<Test>::get(66)[0];
+        assert_eq!((liability.from, liability.to, liability.amount, liability.unlock_epoch), (55, 1, 300, 2));
+
+        let offenders = vec![OffenceDetails { offender: (55, 500), reporters: vec![] }];
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::from_percent(10)],
+            0,
+        );
+
+        // the deferred slash takes 10% of the moved stake from the delegation to 1
+        run_to_block(200);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        assert_eq!(Delegators::<Test>::get(66, 1).unwrap().amount, 270);
+        assert_eq!(ValidatorStakes::<Test>::get(1), stake_of_1 + 270);
+        assert_eq!(Balances::reserved_balance(66), 270);
+        System::assert_has_event(Event::Slashed { who: 66, validator: 55, amount: 30 }.into());
+        assert_eq!(RedelegationLiabilities::<Test>::get(66)[0].amount, 270);
+
+        // once the unbonding period is over the moved stake is no longer liable
+        run_to_block(300);
+        assert_ok!(Dpos::force_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        assert_eq!(Delegators::<Test>::get(66, 1).unwrap().amount, 270);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(405));
+    });
+}
+
+// test moving the whole delegation removes the old one
+#[test]
+fn redelegate_everything() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(77), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        assert_ok!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 77, 300));
+
+        assert!(!Delegators::<Test>::contains_key(66, 55));
+        assert_eq!(Delegators::<Test>::get(66, 77).unwrap().amount, 300);
+        assert_eq!(ValidatorStakes::<Test>::get(55), 500);
+        assert_eq!(ValidatorStakes::<Test>::get(77), 800);
+    });
+}
+
+// test invalid redelegations
+#[test]
+fn redelegate_invalid() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 55, 100), Error::<Test>::SameValidator);
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 99, 100), Error::<Test>::ValidatorNotFound);
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 2, 1, 100), Error::<Test>::NoDelegationFound);
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 400), Error::<Test>::InsufficientBalance);
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 0), Error::<Test>::InvalidAmount);
+    });
+}
+
+// test the redelegation rate limit
+#[test]
+fn redelegate_rate_limited() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 100));
+
+        // RedelegationCooldown is 2 epochs in the mock
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 55, 1, 100), Error::<Test>::RedelegationTooSoon);
+        run_to_block(100);
+        assert_noop!(Dpos::redelegate(RuntimeOrigin::signed(66), 1, 55, 100), Error::<Test>::RedelegationTooSoon);
+
+        run_to_block(200);
+        assert_ok!(Dpos::redelegate(RuntimeOrigin::signed(66), 1, 55, 100));
+        assert_eq!(LastRedelegation::<Test>::get(66), Some(2));
+    });
+}
+
+// ------- unbonding tests -------
+
+// undelegated stake stays held in an unlocking chunk until the unbonding period is over
+#[test]
+fn undelegate_creates_unlocking_chunk() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 100));
+
+        // funds are not released yet
+        assert_eq!(Balances::free_balance(66), 700);
+
+        let chunks = Unlocking::<Test>::get(66);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].validator, 55);
+        assert_eq!(chunks[0].reason, HoldReason::Delegation);
+        assert_eq!(chunks[0].amount, 100);
+        assert_eq!(chunks[0].unlock_epoch, 2); // epoch 0 + UnbondingEpochs
+
+        assert!(System::events().iter().any(|record| {
+            record.event == RuntimeEvent::Dpos(Event::Unbonded { who: 66, validator: 55, amount: 100, unlock_epoch: 2 })
+        }));
+
+        // a second undelegation in the same epoch is merged into the same chunk
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 50));
+        let chunks = Unlocking::<Test>::get(66);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].amount, 150);
+    });
+}
+
+// withdrawing is only possible after the unbonding period
+#[test]
+fn withdraw_unbonded_after_unbonding_period() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 300));
+
+        // still unbonding
+        run_to_block(199);
+        assert_noop!(
+            Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)),
+            Error::<Test>::NothingToWithdraw
+        );
+
+        run_to_block(200);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_eq!(Balances::free_balance(66), 1_000);
+        assert!(!Unlocking::<Test>::contains_key(66));
+        System::assert_last_event(Event::Withdrawn { who: 66, amount: 300 }.into());
+
+        // nothing left in the queue
+        assert_noop!(
+            Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)),
+            Error::<Test>::NoUnlockingChunks
+        );
+    });
+}
+
+// only the matured chunks are released, the rest stays in the queue
+#[test]
+fn withdraw_unbonded_keeps_immature_chunks() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 100)); // unlocks at epoch 2
+
+        run_to_block(101);
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 100)); // unlocks at epoch 3
+        assert_eq!(Unlocking::<Test>::get(66).len(), 2);
+
+        run_to_block(200);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        System::assert_last_event(Event::Withdrawn { who: 66, amount: 100 }.into());
+
+        let chunks = Unlocking::<Test>::get(66);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].unlock_epoch, 3);
+    });
+}
+
+// unregistering starts unbonding the self-stake and the stake of every delegator
+#[test]
+fn unregister_validator_unbonds_stake() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
+
+        let chunks = Unlocking::<Test>::get(55);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].reason, HoldReason::ValidatorRegistration);
+        assert_eq!(chunks[0].amount, 500);
+        assert_eq!(Unlocking::<Test>::get(66)[0].amount, 300);
+
+        run_to_block(200);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(55)));
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_eq!(Balances::free_balance(55), 1_000);
+        assert_eq!(Balances::free_balance(66), 1_000);
+    });
+}
+
+// a slash that lands while the self-stake is unbonding still burns it
+#[test]
+fn slash_during_unbonding() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
+
+        run_to_block(150); // mid-unbonding
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()));
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::one(), amount: 500 }.into());
+
+        // the slashed stake can not be withdrawn anymore
+        run_to_block(200);
+        assert_noop!(
+            Dpos::withdraw_unbonded(RuntimeOrigin::signed(55)),
+            Error::<Test>::NoUnlockingChunks
+        );
+        assert_eq!(Balances::free_balance(55), 500);
+    });
+}
+
+// the unlocking queue is bounded
+#[test]
+fn too_many_unlocking_chunks() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        // one chunk per epoch, withdrawal never called
+        let max_chunks: u32 = <Test as crate::Config>::MaxUnlockingChunks::get();
+        let max_chunks = max_chunks as u64;
+        for epoch in 0..max_chunks {
+            run_to_block(epoch * 100 + 1);
+            assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 1));
+        }
+
+        run_to_block(max_chunks * 100 + 1);
+        assert_noop!(
+            Dpos::undelegate(RuntimeOrigin::signed(66), 55, 1),
+            Error::<Test>::TooManyUnlockingChunks
+        );
+
+        // withdrawing the matured chunks frees up space
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 1));
+    });
+}
+
+// test a full unlocking queue of a delegator does not block unregistering the validator, the delegation is merged into
+// the delegator's last chunk for the validator or into its last chunk otherwise
+#[test]
+fn unregister_with_full_unlocking_queue() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 200));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 1, 300));
+
+        // 66 fills its queue unbonding from 55, 77 unbonding from 1
+        let max_chunks: u32 = <Test as crate::Config>::MaxUnlockingChunks::get();
+        let max_chunks = max_chunks as u64;
+        for epoch in 0..max_chunks {
+            run_to_block(epoch * 100 + 1);
+            assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 1));
+            assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(77), 1, 1));
+        }
+
+        run_to_block(max_chunks * 100 + 1);
+        assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
+        let unlock_epoch = max_chunks + 2;
+
+        assert!(Delegators::<Test>::get(66, 55).is_none());
+        let chunks = Unlocking::<Test>::get(66);
+        assert_eq!(chunks.len() as u64, max_chunks);
+        let last = chunks.last().unwrap();
+        assert_eq!((last.validator, last.amount, last.unlock_epoch), (55, 1 + 300 - max_chunks as u128, unlock_epoch));
+
+        assert!(Delegators::<Test>::get(77, 55).is_none());
+        let chunks = Unlocking::<Test>::get(77);
+        assert_eq!(chunks.len() as u64, max_chunks);
+        let last = chunks.last().unwrap();
+        assert_eq!((last.validator, last.amount, last.unlock_epoch), (1, 1 + 200, unlock_epoch));
+
+        // the merged stake stays held until the chunk matures
+        assert_eq!(Balances::free_balance(66), 700);
+        assert_eq!(Balances::free_balance(77), 500);
+        run_to_block(unlock_epoch * 100 + 1);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(77)));
+        assert_eq!(Balances::free_balance(77), 500 + max_chunks as u128 + 200);
+    });
+}
+
+// ------ update validator set tests -------
+
+// test both election strategies agree when every delegator backs a single validator
+#[test]
+fn election_strategies_agree_on_single_delegations() {
+    use crate::election::{ElectionStrategy, SequentialPhragmen, TopStake};
+
+    let candidates: Vec<(u64, u128)> = vec![(1, 100), (2, 100), (3, 100), (4, 100)];
+    let delegations: Vec<(u64, u64, u128)> = vec![(11, 1, 400), (12, 2, 300), (13, 3, 200), (14, 4, 100)];
+
+    let top = <TopStake as ElectionStrategy<u64, u128>>::elect(candidates.clone(), delegations.clone(), 2);
+    let mut phragmen = <SequentialPhragmen as ElectionStrategy<u64, u128>>::elect(candidates, delegations, 2);
+    phragmen.sort();
+
+    assert_eq!(top, vec![1, 2]);
+    assert_eq!(phragmen, vec![1, 2]);
+}
+
+// test sequential Phragmén gives a seat to stake spread by many accounts where top-N hands every seat to a whale
+#[test]
+fn election_phragmen_differs_from_top_stake_on_whale() {
+    use crate::election::{ElectionStrategy, SequentialPhragmen, TopStake};
+
+    let candidates: Vec<(u64, u128)> = vec![(1, 200), (2, 100), (3, 200), (4, 100)];
+    // the whale 99 backs 1 and 2 with 12_000, three accounts back 3 and 4 with 7_500 together
+    let delegations: Vec<(u64, u64, u128)> = vec![
+        (99, 1, 6_000),
+        (99, 2, 6_000),
+        (11, 3, 1_250),
+        (11, 4, 1_250),
+        (12, 3, 1_250),
+        (12, 4, 1_250),
+        (13, 3, 1_250),
+        (13, 4, 1_250),
+    ];
+
+    // 1 and 2 are backed the most one by one, top-N gives the whale both seats
+    let mut top = <TopStake as ElectionStrategy<u64, u128>>::elect(candidates.clone(), delegations.clone(), 2);
+    top.sort();
+    assert_eq!(top, vec![1, 2]);
+
+    // the whale is one voter and its load grows with its first seat, the other accounts win the second one
+    let mut phragmen = <SequentialPhragmen as ElectionStrategy<u64, u128>>::elect(candidates, delegations, 2);
+    phragmen.sort();
+    assert_eq!(phragmen, vec![1, 3]);
+}
+
+// test an account backing several validators is a single voter for all of them
+#[test]
+fn election_ballots_one_per_account() {
+    use crate::election::ballots;
+
+    let candidates: Vec<(u64, u128)> = vec![(1, 100), (2, 100), (3, 100)];
+    // the whale 99 backs 1 and 2, the validator 3 also backs 1, the delegation to 42 is not a candidate's
+    let delegations: Vec<(u64, u64, u128)> = vec![(99, 1, 10_000), (99, 2, 10), (3, 1, 50), (11, 3, 4_900), (11, 42, 700)];
+
+    let voters = ballots(&candidates, &delegations);
+    assert_eq!(
+        voters,
+        vec![
+            (1, 100, vec![1]),
+            (2, 100, vec![2]),
+            (3, 150, vec![3, 1]),
+            (11, 4_900, vec![3]),
+            (99, 10_010, vec![1, 2]),
+        ]
+    );
+}
+
+// test the strategies respect the number of seats and ignore unknown validators
+#[test]
+fn election_strategies_bounds() {
+    use crate::election::{ElectionStrategy, SequentialPhragmen, TopStake};
+
+    let candidates: Vec<(u64, u128)> = vec![(1, 100), (2, 200)];
+    let delegations: Vec<(u64, u64, u128)> = vec![(11, 1, 500), (12, 42, 10_000)];
+
+    let top = <TopStake as ElectionStrategy<u64, u128>>::elect(candidates.clone(), delegations.clone(), 5);
+    let mut phragmen = <SequentialPhragmen as ElectionStrategy<u64, u128>>::elect(candidates.clone(), delegations.clone(), 5);
+    phragmen.sort();
+    assert_eq!(top, vec![1, 2]);
+    assert_eq!(phragmen, vec![1, 2]);
+
+    assert_eq!(<TopStake as ElectionStrategy<u64, u128>>::elect(candidates.clone(), delegations.clone(), 1), vec![1]);
+    assert_eq!(<SequentialPhragmen as ElectionStrategy<u64, u128>>::elect(candidates, delegations, 1), vec![1]);
+}
+
+// test the pallet exposes targets and voters to the election provider machinery
+#[test]
+fn election_data_provider() {
+    use codec::Encode;
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 1, 200));
+        Balances::make_free_balance_be(&10, 1_000);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(10), 55, 100));
+
+        // the 10 genesis validators and 55 are electable
+        let targets = <Dpos as ElectionDataProvider>::electable_targets(DataProviderBounds::default()).unwrap();
+        assert_eq!(targets.len(), 11);
+        assert!(targets.contains(&55));
+
+        // every account is a single voter, with its self-stake and all its delegations for all the validators it backs
+        let voters = <Dpos as ElectionDataProvider>::electing_voters(DataProviderBounds::default()).unwrap();
+        assert_eq!(voters.len(), 12);
+        let vote_of = |account: u64| {
+            let mut votes: Vec<(u64, Vec<u64>)> = voters
+                .iter()
+                .filter(|(who, _, _)| *who == account)
+                .map(|(_, weight, votes)| (*weight, votes.to_vec()))
+                .collect();
+            assert_eq!(votes.len(), 1);
+            let (weight, mut targets) = votes.remove(0);
+            targets.sort();
+            (weight, targets)
+        };
+        assert_eq!(vote_of(55), (500, vec![55]));
+        assert_eq!(vote_of(66), (500, vec![1, 55]));
+        assert_eq!(vote_of(10), (200, vec![10, 55]));
+
+        // bounds limit the number of voters and their encoded size
+        let bounds = DataProviderBounds { count: Some(5.into()), size: None };
+        assert_eq!(<Dpos as ElectionDataProvider>::electing_voters(bounds).unwrap().len(), 5);
+        let voter_size = voters[0].encoded_size() as u32;
+        let bounds = DataProviderBounds { count: None, size: Some((voter_size * 3).into()) };
+        assert_eq!(<Dpos as ElectionDataProvider>::electing_voters(bounds).unwrap().len(), 3);
+        let bounds = DataProviderBounds { count: None, size: Some((voter_size * 3 - 1).into()) };
+        assert_eq!(<Dpos as ElectionDataProvider>::electing_voters(bounds).unwrap().len(), 2);
+
+        assert_eq!(<Dpos as ElectionDataProvider>::desired_targets(), Ok(10));
+        assert_eq!(<Dpos as ElectionDataProvider>::next_election_prediction(150), 200);
+    });
+}
+
+parameter_types! {
+    pub TestElectionBounds: ElectionBounds = ElectionBoundsBuilder::default().build();
+}
+
+// on-chain sequential Phragmén from frame_election_provider_support, fed by the pallet
+struct OnChainPhragmen;
+impl onchain::Config for OnChainPhragmen {
+    type System = Test;
+    type Solver = SequentialPhragmen<u64, Perbill>;
+    type DataProvider = Dpos;
+    type WeightInfo = ();
+    type MaxWinners = MaxValidators;
+    type Bounds = TestElectionBounds;
+}
+
+// test the standard on-chain provider and the pallet's own provider elect from the same data
+#[test]
+fn election_providers_elect_from_pallet_data() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 1, 200));
+
+        let onchain = onchain::OnChainExecution::<OnChainPhragmen>::elect().unwrap();
+        let own = <Test as Config>::ElectionProvider::elect().unwrap();
+
+        // 11 candidates for 10 seats, 55 and 1 have the most stake and are elected by both
+        assert_eq!(onchain.len(), 10);
+        assert_eq!(own.len(), 10);
+        assert!(onchain.iter().any(|(who, _)| *who == 55));
+        assert!(onchain.iter().any(|(who, _)| *who == 1));
+
+        // 66 is a single voter, the on-chain supports carry all of its stake and the winners' stake as a whole
+        let backing_of_66: u128 = onchain
+            .iter()
+            .flat_map(|(_, support)| support.voters.iter())
+            .filter(|(who, _)| *who == 66)
+            .map(|(_, amount)| *amount)
+            .sum();
+        assert_eq!(backing_of_66, 500);
+        let onchain_total: u128 = onchain.iter().map(|(_, support)| support.total).sum();
+        let winners_stake: u128 = onchain.iter().map(|(who, _)| ValidatorStakes::<Test>::get(who)).sum();
+        assert_eq!(onchain_total, winners_stake);
+
+        // the own provider's supports are the delegations, exactly the stake of each winner
+        for (who, support) in own.iter() {
+            assert_eq!(support.total, ValidatorStakes::<Test>::get(who));
+        }
+        let (_, support) = own.iter().find(|(who, _)| *who == 55).unwrap();
+        assert_eq!(support.total, 800);
+        assert_eq!(support.voters.len(), 2);
+
+        // the elected set is what update_validators stores, the stake exposed with it is the delegations
+        run_to_block(100);
+        assert!(CurrentValidators::<Test>::get().contains(&55));
+        assert!(CurrentValidators::<Test>::get().contains(&1));
+        assert_eq!(SnapshotDelegators::<Test>::get((1, 66, 55)).unwrap().amount, 300);
+        assert_eq!(SnapshotDelegators::<Test>::get((1, 66, 1)).unwrap().amount, 200);
+    });
+}
+
+// test for validator set update based on total stake
+// add 10 validators and for each validator 3 delegators,select the top 5 validators 
+// then stake more and update the validator set again and check if the top 5 validators upd
+// added dynamic testing and removed hardcoding - tested for a large number of validators
+// prints the logs from the functions in order to check that everything is working as expected
+// took out the assert_eq! for the validators as it was failing due to the randomness of the stake
+
+#[test]
+fn update_validator_set() {
+    new_test_ext().execute_with(|| {
+
+		let _ = env_logger::builder().is_test(true).try_init();
+
+        run_to_block(99);
+
+        let pool: u64 = 20;
+
+        // Fund accounts with sufficient balance
+        for i in 11..=pool {
+            Balances::make_free_balance_be(&i, 1_000);
+        }
+
+        // Register validators
+        for i in 11..=pool {
+            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(i), 100, Perbill::from_percent(30)));
+        }
+
+        // Delegate to validators
+        for i in 11..=pool {
+            for j in 11..=15 {
+                let num = rand::thread_rng().gen_range(9..50); // Generate random number in the range
+                let delegator = j * pool + i; // create unique delegator ids
+                Balances::make_free_balance_be(&delegator, 10_000);
+                let amount = (i * num).into(); // delegate different amounts based on `i`
+                assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), i, amount));
+            }
+        }
+
+        // Advance to the next block to trigger validator update
+        run_to_block(100);
+
+        // Verify storage updates
+        let current_validators = CurrentValidators::<Test>::get();
+        // log::info!("Current validators: {:?}", current_validators);
+        let max_validators = <Test as crate::Config>::MaxValidators::get() as usize;
+        assert_eq!(current_validators.len(), max_validators);
+		//let expected_validators: Vec<u64> = (6..=15).rev().collect();
+        let max_validators_u64 = max_validators as u64;
+        //let expected_validators: Vec<u64> = ((pool - max_validators_u64 + 1)..=pool).rev().collect();
+        //assert_eq!(current_validators.into_inner(), expected_validators);
+
+        // Verify event emission
+		let events = System::events();
+        assert!(events.iter().any(|record| {
+            matches!(record.event, RuntimeEvent::Dpos(Event::ValidatorsUpdated))
+        }));
+
+		// Print total stake for each validator
+        print_total_delegated_stake_for_elected::<Test>();
+
+		let poor: u64 = (pool - max_validators_u64)/2;
+        // Second round: Add more stake to the lower ranked validators
+        for i in 11..=poor {
+            for j in 11..=13 {
+                let num = rand::thread_rng().gen_range(9..50); // Generate random number in the range 
+                let delegator = j * pool + i; // same delegators
+                assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), i, (i * num).into()));
+            }
+        }
+
+		// advance to the next block to trigger validator update
+		run_to_block(200);
+
+		// Verify storage updates
+		let current_validators = CurrentValidators::<Test>::get();
+		// log::info!("Current validators: {:?}", current_validators);
+		assert_eq!(current_validators.len(), max_validators);
+
+		// Verify event emission
+		let events = System::events();
+        assert!(events.iter().any(|record| {
+            matches!(record.event, RuntimeEvent::Dpos(Event::ValidatorsUpdated))
+        }));
+
+        print_total_delegated_stake_for_elected::<Test>();
+
+    });
+}
+
+// test fair epoch reward distribution for validators and delegators
+#[test]
+fn delegators_receive_rewards_only_after_next_epoch() {
+    new_test_ext().execute_with(|| {
+        let _ = env_logger::builder().is_test(true).try_init();
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000); // Validator
+        Balances::make_free_balance_be(&66, 1_000); // Delegator 1
         Balances::make_free_balance_be(&77, 1_000); // Delegator 2
         Balances::make_free_balance_be(&88, 1_000); // Delegator 3
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
-        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300)); // delegator 1 delegates beginning of epoch
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300)); // delegator 1 delegates beginning of epoch
+
+        let initial_b_validator = Balances::free_balance(55); 
+        let initial_b_delegator1 = Balances::free_balance(66); // balance delegator 1 after staking
+
+        run_to_block(150);  // Advance to half of the epoch duration
+
+        // delegato 2 delegates in the middle of the epoch
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 200));
+        let initial_b_delegator2 = Balances::free_balance(77); // balance after delegation
+
+        run_to_block(190); // go to just before end of epoch 
+
+        // still in current epoch, nothing can be claimed yet
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(66), 1, 55),
+            Error::<Test>::EpochNotFinished
+        );
+       
+        run_to_block(200); // go to next epoch
+
+        // Delegator 1 and the validator claim the rewards for epoch 1
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(66), 1, 55));
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(55), 1, 55));
+        assert!(Balances::free_balance(66) > initial_b_delegator1); // Delegator 1 should receive rewards
+        assert!(Balances::free_balance(55) > initial_b_validator); // Validator should receive rewards
+
+        // Delegator 2 was not in the snapshot of epoch 1
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 55),
+            Error::<Test>::NothingToClaim
+        );
+
+        run_to_block(250);
+
+        // delegato 3 delegates in the middle of the epoch
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(88), 55, 200));
+        let initial_b_delegator3 = Balances::free_balance(88); // balance after delegation
+
+        run_to_block(300); // go to next epoch
+
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 2, 55));
+        assert!(Balances::free_balance(77) > initial_b_delegator2); // now he should receive rewards
+
+        // Delegator 3 was not in the snapshot of epoch 2
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(88), 2, 55),
+            Error::<Test>::NothingToClaim
+        );
+
+        run_to_block(400); 
+
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(88), 3, 55));
+        assert!(Balances::free_balance(88) > initial_b_delegator3); // now he should receive rewards
+
+    });
+}
+
+// rewards are only recorded at the end of the epoch, nothing is minted until they are claimed
+#[test]
+fn rewards_are_recorded_not_minted() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        let initial_b_validator = Balances::free_balance(1);
+
+        run_to_block(100);
+
+        // genesis validator authored blocks in epoch 0, its pot is recorded but not paid
+        let pot = EpochRewards::<Test>::get(0, 1).unwrap();
+        assert!(!pot.validator_reward.is_zero());
+        assert_eq!(Balances::free_balance(1), initial_b_validator);
+
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(1), 0, 1));
+        assert_eq!(Balances::free_balance(1), initial_b_validator + validator_claim(&pot));
+        System::assert_last_event(Event::RewardsClaimed { who: 1, validator: 1, epoch: 0, amount: validator_claim(&pot) }.into());
+    });
+}
+
+// rewards can only be claimed once
+#[test]
+fn claim_rewards_twice() {
+    new_test_ext().execute_with(|| {
+        run_to_block(100);
+
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(1), 0, 1));
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(1), 0, 1),
+            Error::<Test>::RewardsAlreadyClaimed
+        );
+    });
+}
+
+// accounts without a share in the pot can not claim
+#[test]
+fn claim_rewards_without_share() {
+    new_test_ext().execute_with(|| {
+        run_to_block(100);
+
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(66), 0, 1),
+            Error::<Test>::NothingToClaim
+        );
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(1), 0, 55),
+            Error::<Test>::NoRewardsForEpoch
+        );
+    });
+}
+
+// unclaimed rewards expire after `HistoryDepth` epochs
+#[test]
+fn claim_rewards_after_history_depth() {
+    new_test_ext().execute_with(|| {
+        run_to_block(100);
+        assert!(EpochRewards::<Test>::contains_key(0, 1));
+
+        // epoch 0 can still be claimed in epoch 4
+        run_to_block(400);
+        assert!(EpochRewards::<Test>::contains_key(0, 1));
+
+        // epoch 0 expired in epoch 5 and its pot is removed
+        run_to_block(500);
+        assert!(!EpochRewards::<Test>::contains_key(0, 1));
+        assert_noop!(
+            Dpos::claim_rewards(RuntimeOrigin::signed(1), 0, 1),
+            Error::<Test>::RewardsExpired
+        );
+    });
+}
+
+// automatic payout pays the snapshot delegators in pages over the following blocks
+#[test]
+fn automatic_payout_in_pages() {
+    new_test_ext().execute_with(|| {
+        AutomaticPayouts::set(true);
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        for delegator in 61..=65 {
+            Balances::make_free_balance_be(&delegator, 1_000);
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), 55, 100));
+        }
+
+        // epoch 0 only has the genesis validators, it is paid in a single page
+        run_to_block(100);
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(0));
+        assert!(ClaimedRewards::<Test>::get((0, 1, 1)));
+
+        // 5 delegators with 2 per page need 3 blocks
+        run_to_block(200);
+        System::assert_has_event(Event::PayoutPageProcessed { epoch: 1, page: 0, delegators: 2 }.into());
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(0));
+        assert_eq!(PayoutCursor::<Test>::get().unwrap().page, 1);
+
+        run_to_block(201);
+        System::assert_has_event(Event::PayoutPageProcessed { epoch: 1, page: 1, delegators: 2 }.into());
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(0));
+
+        run_to_block(202);
+        System::assert_has_event(Event::PayoutPageProcessed { epoch: 1, page: 2, delegators: 1 }.into());
+        System::assert_last_event(Event::EpochFinalized { epoch: 1 }.into());
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(1));
+        assert!(PayoutCursor::<Test>::get().is_none());
+
+        // everyone was paid exactly once
+        for delegator in 61..=65 {
+            assert!(ClaimedRewards::<Test>::get((1, 55, delegator)));
+            assert!(Balances::free_balance(delegator) > 900);
+            assert_noop!(
+                Dpos::claim_rewards(RuntimeOrigin::signed(delegator), 1, 55),
+                Error::<Test>::RewardsAlreadyClaimed
+            );
+        }
+        assert!(ClaimedRewards::<Test>::get((1, 55, 55)));
+
+        // nothing to do until the next epoch ends
+        run_to_block(250);
+        assert!(PayoutCursor::<Test>::get().is_none());
+    });
+}
+
+// the weight of a block grows with the rewards its payout page pays
+#[test]
+fn automatic_payout_page_weight() {
+    use frame_support::weights::constants::RocksDbWeight;
+
+    new_test_ext().execute_with(|| {
+        AutomaticPayouts::set(true);
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        for delegator in 61..=65 {
+            Balances::make_free_balance_be(&delegator, 1_000);
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), 55, 100));
+        }
+
+        // nothing to pay, only the cursor and the last finalized epoch are read
+        run_to_block(150);
+        System::set_block_number(151);
+        assert_eq!(Dpos::on_initialize(151), RocksDbWeight::get().reads(2));
+
+        // a middle page of epoch 1 pays 2 delegators
+        run_to_block(200);
+        System::set_block_number(201);
+        let claim = RocksDbWeight::get().reads_writes(9, 8);
+        assert_eq!(Dpos::on_initialize(201), RocksDbWeight::get().reads_writes(1 + 3, 2) + claim * 2);
+    });
+}
+
+// a payout whose epoch is pruned before its last page is given up with an event, the next epoch is paid instead
+#[test]
+fn automatic_payout_expired() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        for delegator in 61..=65 {
+            Balances::make_free_balance_be(&delegator, 1_000);
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), 55, 100));
+        }
+
+        // the payout of epoch 1 stalled after its first page and epoch 1 is older than `HistoryDepth` now
+        run_to_block(600);
+        PayoutCursor::<Test>::put(PayoutProgress { epoch: 1, last_delegation: Some((62, 55)), page: 1 });
+        AutomaticPayouts::set(true);
+
+        run_to_block(601);
+        System::assert_last_event(Event::PayoutExpired { epoch: 1, page: 1 }.into());
+        assert!(PayoutCursor::<Test>::get().is_none());
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(1));
+        assert!(!ClaimedRewards::<Test>::get((1, 55, 63)));
+
+        // the payout goes on with the oldest epoch that is still kept
+        run_to_block(602);
+        System::assert_has_event(Event::PayoutPageProcessed { epoch: 2, page: 0, delegators: 2 }.into());
+    });
+}
+
+// delegators who claimed before the automatic payout reached them are skipped
+#[test]
+fn automatic_payout_skips_claimed_rewards() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        for delegator in 61..=65 {
+            Balances::make_free_balance_be(&delegator, 1_000);
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(delegator), 55, 100));
+        }
+
+        // claim manually before automatic payouts are turned on
+        run_to_block(200);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(61), 1, 55));
+        let balance_after_claim = Balances::free_balance(61);
+
+        AutomaticPayouts::set(true);
+        run_to_block(205);
+
+        // both epochs were paid out and finalized, the claimed reward was not paid twice
+        assert_eq!(LastFinalizedEpoch::<Test>::get(), Some(1));
+        assert_eq!(Balances::free_balance(61), balance_after_claim);
+        assert!(ClaimedRewards::<Test>::get((1, 55, 65)));
+    });
+}
+
+// the rounding dust of the pools goes to `RewardRemainder`, every unit is either claimed or handed over
+#[test]
+fn reward_remainder_handled() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        // uneven shares of validator 1's stake, so the claims round down
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 1, 50));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 1, 70));
+
+        // every validator authors the same number of blocks, so the pools are the same on every run
+        run_to_block_offline(100, &[]);
+        let remainder_before = Balances::free_balance(RemainderAccount::get());
+        run_to_block_offline(200, &[]);
+        let remainder = Balances::free_balance(RemainderAccount::get()) - remainder_before;
+
+        // one unit of validator 1's delegators pool can not be split between 100, 50 and 70
+        let pool = EpochRewards::<Test>::get(1, 1).unwrap().delegators_reward;
+        assert_eq!(pool - [100, 50, 70].iter().map(|stake| pool * stake / 220).sum::<u128>(), 1);
+        assert_eq!(remainder, 1);
+        System::assert_has_event(Event::UnclaimableRewards { epoch: 1, amount: 1 }.into());
+
+        // claim every share of epoch 1, the validators' and the delegators'
+        let mut claimed = 0;
+        let validators: Vec<u64> = EpochRewards::<Test>::iter_prefix(1).map(|(validator, _)| validator).collect();
+        for (who, validator) in validators.iter().map(|validator| (*validator, *validator)).chain([(66, 1), (77, 1)]) {
+            let before = Balances::free_balance(who);
+            if Dpos::claim_rewards(RuntimeOrigin::signed(who), 1, validator).is_ok() {
+                claimed += Balances::free_balance(who) - before;
+            }
+        }
+
+        let total: u128 = EpochRewards::<Test>::iter_prefix(1).map(|(_, pot)| pot.validator_reward + pot.delegators_reward).sum();
+        assert_eq!(claimed + remainder, total);
+    });
+}
+
+// self-stake bonded in the last block of an epoch does not take a bigger slice of that epoch's delegators pool
+#[test]
+fn late_bond_extra_keeps_split() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 10_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 500));
+
+        run_to_block(199);
+        assert_eq!(SnapshotSelfStakes::<Test>::get(1, 55), Some(500));
+        assert_ok!(Dpos::bond_extra(RuntimeOrigin::signed(55), 4_000));
+        run_to_block(200);
+
+        // the pool is still split half and half between the self-stake and the delegation of the snapshot
+        let pot = EpochRewards::<Test>::get(1, 55).unwrap();
+        assert_eq!((pot.self_stake, pot.total_stake), (500, 1_000));
+        let before = Balances::free_balance(66);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(66), 1, 55));
+        assert_eq!(Balances::free_balance(66) - before, pot.delegators_reward / 2);
+
+        // the extra self-stake only counts from the next snapshot on
+        assert_eq!(SnapshotSelfStakes::<Test>::get(2, 55), Some(4_500));
+    });
+}
+
+// rewards are transferred from the reward pot account instead of minted, the total issuance does not change
+#[test]
+fn rewards_paid_from_pot() {
+    new_test_ext().execute_with(|| {
+        RewardFunding::set(RewardSource::Pot);
+        let pot = Dpos::reward_pot_account();
+        Balances::make_free_balance_be(&pot, 1_000_000);
+
+        // inflows through the `OnUnbalanced` handler land in the pot
+        RewardPotInflow::<Test>::on_unbalanced(<Balances as frame_support::traits::fungible::Balanced<u64>>::issue(500));
+        assert_eq!(Balances::free_balance(pot), 1_000_500);
+
+        run_to_block(200);
+        let (validator, recorded) = EpochRewards::<Test>::iter_prefix(1).next().unwrap();
+        assert_eq!(recorded.unpaid, validator_claim(&recorded));
+        let committed = CommittedRewards::<Test>::get();
+        let pot_before = Balances::free_balance(pot);
+        let issuance = Balances::total_issuance();
+
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(validator), 1, validator));
+        assert_eq!(Balances::total_issuance(), issuance);
+        assert_eq!(Balances::free_balance(pot), pot_before - validator_claim(&recorded));
+        assert_eq!(CommittedRewards::<Test>::get(), committed - validator_claim(&recorded));
+        assert!(EpochRewards::<Test>::get(1, validator).unwrap().unpaid.is_zero());
+    });
+}
+
+// with the reward pot the rounding dust is withdrawn from the pot and handed to `RewardRemainder`
+#[test]
+fn pot_reward_remainder_withdrawn() {
+    new_test_ext().execute_with(|| {
+        RewardFunding::set(RewardSource::Pot);
+        let pot = Dpos::reward_pot_account();
+        Balances::make_free_balance_be(&pot, 1_000_000);
+        run_to_block(1);
+
+        // uneven shares of validator 1's stake, so the claims round down
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 1, 50));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 1, 70));
+
+        // every validator authors the same number of blocks, so the pools are the same on every run
+        run_to_block_offline(100, &[]);
+        let remainder_before = Balances::free_balance(RemainderAccount::get());
+        let pot_before = Balances::free_balance(pot);
+        let committed_before = CommittedRewards::<Test>::get();
+        run_to_block_offline(200, &[]);
+
+        // two units of validator 1's delegators pool can not be split between 100, 50 and 70
+        let pool = EpochRewards::<Test>::get(1, 1).unwrap().delegators_reward;
+        assert_eq!(pool - [100, 50, 70].iter().map(|stake| pool * stake / 220).sum::<u128>(), 2);
+        assert_eq!(Balances::free_balance(RemainderAccount::get()) - remainder_before, 2);
+        System::assert_has_event(Event::UnclaimableRewards { epoch: 1, amount: 2 }.into());
+        assert_eq!(Balances::free_balance(pot), pot_before - 2);
+        let unpaid: u128 = EpochRewards::<Test>::iter_prefix_values(1).map(|pot| pot.unpaid).sum();
+        assert_eq!(CommittedRewards::<Test>::get(), committed_before + unpaid);
+    });
+}
+
+// a pot that cannot cover the epoch scales every reward down and never promises more than it holds
+#[test]
+fn reward_pot_depleted_scales_rewards() {
+    new_test_ext().execute_with(|| {
+        RewardFunding::set(RewardSource::Pot);
+        let pot = Dpos::reward_pot_account();
+        // one unit stays behind to keep the pot alive
+        Balances::make_free_balance_be(&pot, 1_001);
+
+        run_to_block(200);
+        assert!(System::events().iter().any(|record| matches!(
+            record.event,
+            RuntimeEvent::Dpos(Event::RewardPotDepleted { available: 1_000, .. })
+        )));
+        assert!(CommittedRewards::<Test>::get() <= 1_000);
+
+        // every recorded reward can still be paid
+        let pots: Vec<(u64, u64)> = EpochRewards::<Test>::iter()
+            .map(|(epoch, validator, _)| (epoch, validator))
+            .collect();
+        for (epoch, validator) in pots {
+            assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(validator), epoch, validator));
+        }
+        assert!(CommittedRewards::<Test>::get().is_zero());
+        assert!(Balances::free_balance(pot) >= 1);
+    });
+}
+
+// ------ reward destination tests -------
+
+// the destination is stored per account, `Free` removes it
+#[test]
+fn set_reward_destination_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        assert_eq!(RewardDestinations::<Test>::get(77), RewardDestination::Free);
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(77), RewardDestination::Account(88)));
+        assert_eq!(RewardDestinations::<Test>::get(77), RewardDestination::Account(88));
+        System::assert_last_event(Event::RewardDestinationSet { who: 77, destination: RewardDestination::Account(88) }.into());
+
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(77), RewardDestination::Free));
+        assert!(!RewardDestinations::<Test>::contains_key(77));
+    });
+}
+
+// a compounded reward is held and added to the delegation and the validator's stake
+#[test]
+fn rewards_compound_into_delegation() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 300));
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(77), RewardDestination::Compound));
+
+        run_to_block_offline(200, &[]);
+
+        let free = Balances::free_balance(77);
+        let held = Balances::reserved_balance(77);
+        let stake = ValidatorStakes::<Test>::get(55);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 55));
+
+        let reward = Delegators::<Test>::get(77, 55).unwrap().amount - 300;
+        assert!(reward > 0);
+        assert_eq!(Balances::free_balance(77), free);
+        assert_eq!(Balances::reserved_balance(77), held + reward);
+        assert_eq!(ValidatorStakes::<Test>::get(55), stake + reward);
+        System::assert_last_event(Event::RewardsClaimed { who: 77, validator: 55, epoch: 1, amount: reward }.into());
+        System::assert_has_event(Event::RewardsCompounded { who: 77, validator: 55, amount: reward }.into());
+    });
+}
+
+// a validator compounds its reward into its self-stake
+#[test]
+fn rewards_compound_into_self_stake() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(55), RewardDestination::Compound));
+
+        run_to_block_offline(200, &[]);
+
+        let pot = EpochRewards::<Test>::get(1, 55).unwrap();
+        let free = Balances::free_balance(55);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(55), 1, 55));
+
+        assert_eq!(Balances::free_balance(55), free);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(500 + validator_claim(&pot)));
+        assert_eq!(ValidatorStakes::<Test>::get(55), 500 + validator_claim(&pot));
+    });
+}
+
+// a reward can be paid to another account
+#[test]
+fn rewards_paid_to_other_account() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 300));
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(77), RewardDestination::Account(88)));
+
+        run_to_block_offline(200, &[]);
+
+        let free = Balances::free_balance(77);
+        let payee = Balances::free_balance(88);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 55));
+
+        assert_eq!(Balances::free_balance(77), free);
+        assert!(Balances::free_balance(88) > payee);
+        assert_eq!(Delegators::<Test>::get(77, 55).unwrap().amount, 300);
+        assert!(ClaimedRewards::<Test>::get((1, 55, 77)));
+    });
+}
+
+// a reward earned with a delegation that is gone stays free
+#[test]
+fn compound_without_delegation_stays_free() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(10)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 300));
+        assert_ok!(Dpos::set_reward_destination(RuntimeOrigin::signed(77), RewardDestination::Compound));
+
+        run_to_block_offline(200, &[]);
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(77), 55, 300));
+
+        let free = Balances::free_balance(77);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(77), 1, 55));
+
+        assert!(Balances::free_balance(77) > free);
+        assert!(!Delegators::<Test>::contains_key(77, 55));
+    });
+}
+
+// ------ reward apportionment tests -------
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    // the shares and the rounding dust add up to the pool, the dust is less than one unit per stake
+    #[test]
+    fn apportion_is_exact(
+        pool in 0u128..u64::MAX as u128,
+        stakes in prop::collection::vec(0u128..1_000_000_000_000_000, 0..20),
+    ) {
+        let (shares, dust) = crate::apportion::apportion(pool, &stakes);
+        prop_assert_eq!(shares.len(), stakes.len());
+        prop_assert_eq!(shares.iter().sum::<u128>() + dust, pool);
+        if stakes.iter().any(|stake| *stake > 0) {
+            prop_assert!(dust < stakes.len() as u128);
+        }
+        // a bigger stake never gets a smaller share
+        for (i, j) in (0..stakes.len()).flat_map(|i| (0..stakes.len()).map(move |j| (i, j))) {
+            if stakes[i] >= stakes[j] {
+                prop_assert!(shares[i] >= shares[j]);
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    // every unit of an epoch's rewards is claimed by the validators, their self-stake and their delegators,
+    // or handed to `RewardRemainder` as rounding dust
+    #[test]
+    fn epoch_rewards_fully_paid(
+        self_stake in 200u128..5_000,
+        delegations in prop::collection::vec(10u128..5_000, 0..4),
+        commission in 0u32..50,
+    ) {
+        new_test_ext().execute_with(|| {
+            run_to_block(1);
+            Balances::make_free_balance_be(&55, 10_000);
+            assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), self_stake, Perbill::from_percent(commission)));
+            let delegators: Vec<u64> = (100..100 + delegations.len() as u64).collect();
+            for (delegator, amount) in delegators.iter().zip(delegations.iter()) {
+                Balances::make_free_balance_be(delegator, 10_000);
+                assert_ok!(Dpos::delegate(RuntimeOrigin::signed(*delegator), 55, *amount));
+            }
+
+            // a delegation that joins mid-epoch is not part of the backing stake
+            run_to_block_offline(150, &[]);
+            Balances::make_free_balance_be(&200, 10_000);
+            assert_ok!(Dpos::delegate(RuntimeOrigin::signed(200), 55, 1_000));
+            let remainder_before = Balances::free_balance(RemainderAccount::get());
+            let committed_before = CommittedRewards::<Test>::get();
+            run_to_block_offline(201, &[]);
+            let remainder = Balances::free_balance(RemainderAccount::get()) - remainder_before;
+
+            let pots: Vec<(u64, RewardPot<Test>)> = EpochRewards::<Test>::iter_prefix(1).collect();
+            let total: u128 = pots.iter().map(|(_, pot)| pot.validator_reward + pot.delegators_reward).sum();
+            let claims = pots
+                .iter()
+                .map(|(validator, _)| (*validator, *validator))
+                .chain(delegators.iter().map(|delegator| (*delegator, 55)))
+                .chain([(200, 55)]);
+            let mut claimed = 0;
+            for (who, validator) in claims {
+                let before = Balances::free_balance(who);
+                let _ = Dpos::claim_rewards(RuntimeOrigin::signed(who), 1, validator);
+                claimed += Balances::free_balance(who) - before;
+            }
+
+            assert_eq!(claimed + remainder, total);
+            assert_eq!(CommittedRewards::<Test>::get(), committed_before);
+        });
+    }
+}
+
+// ------ inflation tests -------
+// the curve climbs from the minimum to the maximum at the ideal ratio and falls back over the falloff
+#[test]
+fn inflation_curve_is_piecewise_linear() {
+    let curve = |ratio: u32| crate::inflation::annual_inflation(
+        Perbill::from_percent(2),
+        Perbill::from_percent(10),
+        Perbill::from_percent(50),
+        Perbill::from_percent(20),
+        Perbill::from_percent(ratio),
+    );
+    assert_eq!(curve(0), Perbill::from_percent(2));
+    assert_eq!(curve(25), Perbill::from_percent(6));
+    assert_eq!(curve(50), Perbill::from_percent(10));
+    assert_eq!(curve(60), Perbill::from_percent(6));
+    assert_eq!(curve(70), Perbill::from_percent(2));
+    assert_eq!(curve(100), Perbill::from_percent(2));
+}
+
+// the rewards of an epoch add up to the epoch payout of the inflation curve
+#[test]
+fn epoch_rewards_follow_inflation() {
+    new_test_ext().execute_with(|| {
+        Balances::make_free_balance_be(&55, 100_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 50_000, Perbill::from_percent(30)));
+
+        run_to_block(50);
+        let staked: u128 = ValidatorStakes::<Test>::iter_values().sum();
+        assert_eq!(Dpos::staking_ratio(), Perbill::from_rational(staked, Balances::total_issuance()));
+        let payout = Dpos::epoch_payout();
+        assert_eq!(payout, Dpos::current_inflation().mul_floor(Balances::total_issuance()));
+
+        run_to_block(150);
+        let recorded: u128 = EpochRewards::<Test>::iter_prefix(0)
+            .map(|(_, pot)| pot.validator_reward + pot.delegators_reward)
+            .sum();
+        // each validator's share rounds down by less than one unit
+        assert!(recorded <= payout);
+        assert!(recorded + 10 >= payout);
+    });
+}
+
+// the APR is the yearly inflation divided by the staking ratio
+#[test]
+fn apr_follows_staking_ratio() {
+    new_test_ext().execute_with(|| {
+        let apr = Dpos::current_apr();
+        assert_eq!(apr, Perbill::from_rational(Dpos::current_inflation().deconstruct(), Dpos::staking_ratio().deconstruct()));
+
+        // more stake lowers the return of each staked unit
+        Balances::make_free_balance_be(&55, 100_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 50_000, Perbill::from_percent(30)));
+        assert!(Dpos::current_apr() < apr);
+    });
+}
+
+// ------ reward curve tests -------
+// validators below the median get a boost, the multiplier follows the stake up to the cap and saturates above it
+#[test]
+fn decentralization_curve_multipliers() {
+    parameter_types! {
+        pub const Boost: Perbill = Perbill::from_percent(20);
+        pub const Cap: Perbill = Perbill::from_percent(25);
+        pub const ZeroCap: Perbill = Perbill::zero();
+    }
+    type Curve = crate::reward_curve::DecentralizationCurve<Boost, Cap>;
+    use crate::reward_curve::RewardCurve;
+    use sp_runtime::FixedU128;
+
+    let median = Perbill::from_percent(10);
+    // full boost with no stake, half of it halfway to the median
+    assert_eq!(Curve::multiplier(Perbill::zero(), median), FixedU128::from_rational(120, 100));
+    assert_eq!(Curve::multiplier(Perbill::from_percent(5), median), FixedU128::from_rational(110, 100));
+    // between the median and the cap every staked token is worth the same
+    assert_eq!(Curve::multiplier(median, median), FixedU128::from_u32(1));
+    assert_eq!(Curve::multiplier(Perbill::from_percent(20), median), FixedU128::from_u32(2));
+    assert_eq!(Curve::multiplier(Perbill::from_percent(25), median), FixedU128::from_rational(250, 100));
+    // above the cap the share counts for less, 25% * (2 - 25% / 50%) and 25% * (2 - 25% / 100%)
+    assert_eq!(Curve::multiplier(Perbill::from_percent(50), median), FixedU128::from_rational(375, 100));
+    assert_eq!(Curve::multiplier(Perbill::from_percent(100), median), FixedU128::from_rational(4375, 1000));
+    // a zero cap and the no-op curve weigh every block the same
+    let flat = crate::reward_curve::DecentralizationCurve::<Boost, ZeroCap>::multiplier;
+    assert_eq!(flat(Perbill::from_percent(50), median), FixedU128::from_u32(1));
+    assert_eq!(<() as RewardCurve>::multiplier(Perbill::from_percent(50), median), FixedU128::from_u32(1));
+}
+
+// from the median on a validator never earns less for a bigger share, but every staked token above the cap earns
+// progressively less, with the cap above or below the median
+#[test]
+fn decentralization_curve_saturates_above_cap() {
+    parameter_types! {
+        pub const Boost: Perbill = Perbill::from_percent(20);
+        pub const HighCap: Perbill = Perbill::from_percent(25);
+        pub const LowCap: Perbill = Perbill::from_percent(5);
+    }
+    use crate::reward_curve::{DecentralizationCurve, RewardCurve};
+    use sp_runtime::FixedU128;
+
+    let median = Perbill::from_percent(10);
+    for (multiplier, cap) in [
+        (DecentralizationCurve::<Boost, HighCap>::multiplier as fn(Perbill, Perbill) -> FixedU128, 25u32),
+        (DecentralizationCurve::<Boost, LowCap>::multiplier, 5),
+    ] {
+        let curve: Vec<(u32, FixedU128)> =
+            (10..=100u32).map(|percent| (percent, multiplier(Perbill::from_percent(percent), median))).collect();
+        assert!(curve.windows(2).all(|pair| pair[0].1 < pair[1].1));
+
+        // the return per percent of stake, flat up to the cap and falling above it
+        let per_stake: Vec<(u32, FixedU128)> =
+            curve.iter().map(|(percent, multiplier)| (*percent, *multiplier / FixedU128::from_u32(*percent))).collect();
+        assert!(per_stake.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert!(per_stake.windows(2).filter(|pair| pair[0].0 >= cap).all(|pair| pair[0].1 > pair[1].1));
+    }
+}
+
+// a validator above the cap earns more than the others, but less per staked token
+#[test]
+fn capped_validator_earns_less_per_stake() {
+    new_test_ext().execute_with(|| {
+        CurveCap::set(Perbill::from_percent(20));
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 10_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 5_000, Perbill::from_percent(30)));
+
+        // every validator authors the same number of blocks in epoch 1
+        run_to_block_offline(150, &[]);
+        assert!(CurrentValidators::<Test>::get().contains(&55));
+        let payout = Dpos::epoch_payout();
+        run_to_block_offline(201, &[]);
+
+        let reward = |validator: u64| {
+            let pot = EpochRewards::<Test>::get(1, validator).unwrap();
+            (pot.validator_reward + pot.delegators_reward, pot.total_stake)
+        };
+        let other = CurrentValidators::<Test>::get().into_iter().find(|validator| *validator != 55).unwrap();
+        let (capped, capped_stake) = reward(55);
+        let (uncapped, uncapped_stake) = reward(other);
+        assert!(capped > uncapped);
+        assert!(capped * uncapped_stake < uncapped * capped_stake);
+
+        let recorded: u128 = EpochRewards::<Test>::iter_prefix(1)
+            .map(|(_, pot)| pot.validator_reward + pot.delegators_reward)
+            .sum();
+        assert!(recorded <= payout);
+        assert!(recorded + 10 >= payout);
+    });
+}
+
+// the stake shares the curve weighs are the ones of the epoch snapshot, stake bonded mid-epoch does not count
+#[test]
+fn reward_curve_uses_snapshot_stake() {
+    new_test_ext().execute_with(|| {
+        CurveCap::set(Perbill::one());
+        run_to_block_offline(150, &[]);
+        Balances::make_free_balance_be(&1, 10_000);
+        assert_ok!(Dpos::bond_extra(RuntimeOrigin::signed(1), 900));
+        run_to_block_offline(201, &[]);
+
+        // every genesis validator had 100 in the snapshot and authored the same number of blocks
+        let reward = |validator: u64| {
+            let pot = EpochRewards::<Test>::get(1, validator).unwrap();
+            pot.validator_reward + pot.delegators_reward
+        };
+        assert_eq!(reward(1), reward(2));
+    });
+}
+
+// ------ reputation tests -------
+// the score averages uptime and tenure and is divided by one plus the slashes
+#[test]
+fn reputation_score_formula() {
+    use crate::reputation::score;
+    assert_eq!(score(Perbill::one(), 4, 4, 0), Perbill::one());
+    assert_eq!(score(Perbill::one(), 8, 4, 0), Perbill::one());
+    assert_eq!(score(Perbill::one(), 2, 4, 0), Perbill::from_percent(75));
+    assert_eq!(score(Perbill::from_percent(50), 4, 4, 1), Perbill::from_rational(375u32, 1000u32));
+    assert_eq!(score(Perbill::zero(), 0, 4, 0), Perbill::zero());
+}
+
+// the validators of the ended epoch are scored from their blocks and tenure, slashes are counted
+#[test]
+fn reputation_updated_each_epoch() {
+    new_test_ext().execute_with(|| {
+        // validator 3 misses every slot of epoch 0
+        run_to_block_offline(101, &[3]);
+
+        let online = Reputation::<Test>::get(1);
+        assert_eq!(online.tenure, 1);
+        assert_eq!(online.uptime, Perbill::one());
+        assert_eq!(online.score, Perbill::from_rational(625u32, 1000u32));
+        let offline = Reputation::<Test>::get(3);
+        assert_eq!(offline.uptime, Perbill::zero());
+        assert_eq!(offline.score, Perbill::from_rational(125u32, 1000u32));
+        assert!(Dpos::reputation_score(&55).is_zero());
+
+        // back online, the uptime average recovers halfway
+        run_to_block_offline(201, &[]);
+        assert_eq!(Reputation::<Test>::get(3).uptime, Perbill::from_percent(50));
+        assert_eq!(Reputation::<Test>::get(3).tenure, 2);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        assert_eq!(Reputation::<Test>::get(55).slashes, 1);
+    });
+}
+
+// with `ReputationWeight` set, a less reputable validator earns less for the same blocks
+#[test]
+fn reputation_weighs_rewards() {
+    new_test_ext().execute_with(|| {
+        ReputationWeight::set(Perbill::one());
+        run_to_block_offline(101, &[3]);
+        run_to_block_offline(201, &[]);
+
+        // both authored the same blocks in epoch 1, validator 3 scores 50% and validator 1 75%
+        let total = |validator: u64| {
+            let pot = EpochRewards::<Test>::get(1, validator).unwrap();
+            pot.validator_reward + pot.delegators_reward
+        };
+        assert_eq!(BlockCount::<Test>::get(3), 0); // reset for the new epoch
+        assert!(total(3) < total(1));
+    });
+}
+
+// on equal stake the election keeps the more reputable validator
+#[test]
+fn reputation_breaks_election_ties() {
+    new_test_ext().execute_with(|| {
+        run_to_block_offline(101, &[3]);
+
+        // same stake as the genesis validators, with a better reputation than validator 3
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 100, Perbill::from_percent(30)));
+        Reputation::<Test>::insert(55, ValidatorReputation { score: Perbill::from_percent(90), ..Default::default() });
+        assert_eq!(Dpos::candidates_by_reputation().first().map(|(who, _)| *who), Some(55));
+
+        run_to_block_offline(201, &[]);
+        let validators = CurrentValidators::<Test>::get();
+        assert!(validators.contains(&55));
+        assert!(!validators.contains(&3));
+    });
+}
+
+// ------ fee sharing tests -------
+// `FeeShare` of the fees goes to the reward pot and is credited to the block author, the rest to `Rest`
+#[test]
+fn fees_credited_to_author() {
+    new_test_ext().execute_with(|| {
+        let pot = Dpos::reward_pot_account();
+        Author::set(7);
+        FeeSink::<Test, ResolveTo<SlashTreasury, Balances>>::on_unbalanced(<Balances as frame_support::traits::fungible::Balanced<u64>>::issue(1_000));
+
+        assert_eq!(EpochFees::<Test>::get(7), 800);
+        assert_eq!(Balances::free_balance(pot), 800);
+        assert_eq!(Balances::free_balance(SlashTreasury::get()), 200);
+    });
+}
+
+// the fee share is added to the author's reward pot and split with its snapshot delegators like the block rewards
+#[test]
+fn fees_shared_with_delegators() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 7, 100));
+
+        run_to_block(150);
+        let pot = Dpos::reward_pot_account();
+        Author::set(7);
+        FeeSink::<Test>::on_unbalanced(<Balances as frame_support::traits::fungible::Balanced<u64>>::issue(10_000));
+        run_to_block(201);
+
+        // commission is 30%, so the delegators pool holds at least 70% of the 8_000 fee share
+        let recorded = EpochRewards::<Test>::get(1, 7).unwrap();
+        assert!(recorded.validator_reward + recorded.delegators_reward >= 8_000);
+        assert!(recorded.delegators_reward >= 5_600);
+        assert!(EpochFees::<Test>::iter().next().is_none());
+        // the claims mint the fees again, so they are burned from the pot when recorded
+        assert!(Balances::free_balance(pot).is_zero());
+
+        let before = Balances::free_balance(66);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(66), 1, 7));
+        assert_eq!(Balances::free_balance(66) - before, recorded.delegators_reward * 100 / recorded.total_stake);
+    });
+}
+
+// fees of an author that earns no reward in the epoch go to `RewardRemainder` instead of staying in the pot
+#[test]
+fn unrecorded_fees_handed_to_remainder() {
+    new_test_ext().execute_with(|| {
+        RewardFunding::set(RewardSource::Pot);
+        let pot = Dpos::reward_pot_account();
+        Balances::make_free_balance_be(&pot, 1_000_000);
+        run_to_block(150);
+
+        // 3_000 is not part of the active set, so it has no reward pot for the epoch
+        Author::set(3_000);
+        FeeSink::<Test>::on_unbalanced(<Balances as frame_support::traits::fungible::Balanced<u64>>::issue(1_000));
+        assert_eq!(EpochFees::<Test>::get(3_000), 800);
+        let remainder_before = Balances::free_balance(RemainderAccount::get());
+        Author::set(7);
+        run_to_block(200);
+
+        assert!(EpochRewards::<Test>::get(1, 3_000).is_none());
+        assert!(EpochFees::<Test>::iter().next().is_none());
+        System::assert_has_event(Event::UnrecordedFees { epoch: 1, amount: 800 }.into());
+        assert!(Balances::free_balance(RemainderAccount::get()) - remainder_before >= 800);
+        // the rewards are only committed, so the pot is back to what it held before the fees
+        assert_eq!(Balances::free_balance(pot), 1_000_000);
+    });
+}
+
+// with the reward pot the fees are paid in full even when the pot cannot cover the block rewards
+#[test]
+fn fees_paid_from_depleted_pot() {
+    new_test_ext().execute_with(|| {
+        RewardFunding::set(RewardSource::Pot);
+        let pot = Dpos::reward_pot_account();
+        // the existential deposit of the pot
+        Balances::make_free_balance_be(&pot, 1);
+
+        run_to_block(150);
+        Author::set(7);
+        FeeSink::<Test>::on_unbalanced(<Balances as frame_support::traits::fungible::Balanced<u64>>::issue(1_000));
+        run_to_block(201);
+
+        assert!(System::events().iter().any(|record| matches!(
+            record.event,
+            RuntimeEvent::Dpos(Event::RewardPotDepleted { epoch: 1, available: 0, .. })
+        )));
+        let recorded = EpochRewards::<Test>::get(1, 7).unwrap();
+        assert_eq!(recorded.validator_reward + recorded.delegators_reward, 800);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(7), 1, 7));
+        assert_eq!(Balances::free_balance(pot), 1 + 800 - validator_claim(&recorded));
+    });
+}
+
+// ----- slash tests -------
+#[test]
+fn slash_validator_successfully() {
+    new_test_ext().execute_with(|| {
+        let _ = env_logger::builder().is_test(true).try_init();
+        run_to_block(1);
+
+        // Fund an account with sufficient balance
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        // Register a validator
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::set_commission(RuntimeOrigin::signed(55), Perbill::from_percent(40)));
+        assert!(PendingCommissions::<Test>::contains_key(55));
+
+        // Slash the validator
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()));
+
+        // Verify storage updates
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert_eq!(ValidatorStakes::<Test>::get(55), Zero::zero());
+        // the commissions of a validator slashed out of the set are cleared like on unregistering
+        assert!(!Commissions::<Test>::contains_key(55));
+        assert!(!PendingCommissions::<Test>::contains_key(55));
+
+        // the delegation is burned as well
+        assert!(Delegators::<Test>::get(66, 55).is_none());
+        assert_eq!(Balances::total_balance(&55), 500);
+        assert_eq!(Balances::total_balance(&66), 700);
+
+        // Verify event emission
+        System::assert_has_event(Event::Slashed { who: 55, validator: 55, amount: 500 }.into());
+        System::assert_has_event(Event::Slashed { who: 66, validator: 55, amount: 300 }.into());
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::one(), amount: 800 }.into());
+
+        // the slashed funds go to the `Slash` handler instead of being burned
+        assert_eq!(Balances::free_balance(SlashTreasury::get()), 800);
+    });
+}
+
+// test a partial slash burns the same fraction of the self-stake and of every delegation
+#[test]
+fn slash_validator_fraction() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 200));
+        // a delegation to another validator is not touched
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 1, 100));
+
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+
+        // the validator stays registered with what is left
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        assert_eq!(ValidatorStakes::<Test>::get(55), 900);
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 270);
+        assert_eq!(Delegators::<Test>::get(77, 55).unwrap().amount, 180);
+        assert_eq!(Delegators::<Test>::get(77, 1).unwrap().amount, 100);
+
+        // the burn comes from the held balances, free balances are untouched
+        assert_eq!(Balances::total_balance(&55), 950);
+        assert_eq!(Balances::total_balance(&66), 970);
+        assert_eq!(Balances::total_balance(&77), 980);
+        assert_eq!(Balances::free_balance(66), 700);
+
+        System::assert_has_event(Event::Slashed { who: 55, validator: 55, amount: 50 }.into());
+        System::assert_has_event(Event::Slashed { who: 66, validator: 55, amount: 30 }.into());
+        System::assert_has_event(Event::Slashed { who: 77, validator: 55, amount: 20 }.into());
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(10), amount: 100 }.into());
+    });
+}
+
+// test delegated stake that is unbonding from the validator is slashed too
+#[test]
+fn slash_validator_unbonding_delegation() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 100));
+
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::from_percent(50)));
+
+        // half of the 300 delegated is burned, from the bonded delegation first
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 50);
+        assert_eq!(Unlocking::<Test>::get(66)[0].amount, 100);
+        assert_eq!(Balances::total_balance(&66), 850);
+        System::assert_has_event(Event::Slashed { who: 66, validator: 55, amount: 150 }.into());
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(50), amount: 400 }.into());
+
+        // the unbonding chunk was not needed to cover the slash, it is withdrawn in full
+        run_to_block(300);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_eq!(Balances::free_balance(66), 800);
+    });
+}
+
+// test the slashed amount of every account is rounded down
+#[test]
+fn slash_validator_rounds_down() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 506, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 106));
+
+        // 30% of 506 and of 106 are 151.8 and 31.8
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::from_percent(30)));
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(355));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 75);
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(30), amount: 182 }.into());
+    });
+}
+
+// test a validator left without self-stake is unregistered and its delegations go through the unbonding period
+#[test]
+fn slash_validator_without_self_stake_left() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::unbond_self(RuntimeOrigin::signed(55), 400));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        // 20% of the 500 exposed self-stake takes all of the 100 still bonded
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::from_percent(20)));
+        System::assert_has_event(Event::Slashed { who: 55, validator: 55, amount: 100 }.into());
+        System::assert_has_event(Event::ValidatorDeregistered { validator: 55 }.into());
+
+        // nothing points at the validator anymore, the rest of the delegation is unbonding from it
+        assert!(PotentialValidators::<Test>::get(55).is_none());
+        assert_eq!(ValidatorStakes::<Test>::get(55), 0);
+        assert!(Delegators::<Test>::get(66, 55).is_none());
+        let chunk = &Unlocking::<Test>::get(66)[0];
+        assert_eq!((chunk.validator, chunk.amount), (55, 240));
+        System::assert_has_event(Event::Undelegated { delegator: 66, validator: 55, amount: 240 }.into());
+
+        // both withdraw what is left after the unbonding period
+        run_to_block(300);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(55)));
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_eq!(Balances::free_balance(55), 900);
+        assert_eq!(Balances::free_balance(66), 940);
+    });
+}
+
+// test for slashing a non registered validator
+#[test]
+fn slash_non_registered_validator() {
+    new_test_ext().execute_with(|| {
+        let _ = env_logger::builder().is_test(true).try_init();
+        run_to_block(1);
+
+        // Attempt to slash a non-registered validator
+        assert_noop!(
+            Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()),
+            Error::<Test>::ValidatorNotFound
+        );
+    });
+}
+
+// test for slashing a validator with zero stake
+#[test]
+fn slash_validator_with_zero_stake() {
+    new_test_ext().execute_with(|| {
+        let _ = env_logger::builder().is_test(true).try_init();
+        run_to_block(1);
+
+        // Fund an account with sufficient balance
+        Balances::make_free_balance_be(&55, 1_000);
+
+        // Register and then unregister a validator
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
-        let initial_b_validator = Balances::free_balance(&55); 
-        let initial_b_delegator1 = Balances::free_balance(&66); // balance delegator 1 after staking
+        // the self-stake is still unbonding, so the slash lands on the unlocking chunk
+        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()));
+        assert!(!Unlocking::<Test>::contains_key(55));
+        assert_eq!(Balances::total_balance(&55), 500);
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::one(), amount: 500 }.into());
 
-        run_to_block(150);  // Advance to half of the epoch duration
+        // nothing left to slash
+        assert_noop!(
+            Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()),
+            Error::<Test>::ValidatorNotFound
+        );
+    });
+}
 
-        // delegato 2 delegates in the middle of the epoch
-        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 200));
-        let initial_b_delegator2 = Balances::free_balance(&77); // balance after delegation
+// Ensure slashing attempts fail for non-validator accounts
+#[test]
+fn slash_non_validator() {
+    new_test_ext().execute_with(|| {
+        let _ = env_logger::builder().is_test(true).try_init();
+        run_to_block(1);
 
-        run_to_block(190); // go to just before end of epoch 
+        // Fund an account with sufficient balance
+        Balances::make_free_balance_be(&55, 1_000);
 
-        let final_b_delegator2 = Balances::free_balance(&77);
-        assert_eq!(final_b_delegator2, initial_b_delegator2);  // still in current epoch, Delegator 2 should not receive rewards until the next epoch
-       
-        run_to_block(200); // go to next epoch
+        // Attempt to slash a non-validator account
+        assert_noop!(
+            Dpos::test_slash_validator(RuntimeOrigin::root(), 55, Perbill::one()),
+            Error::<Test>::ValidatorNotFound
+        );
+    });
+}
 
-        // Verify rewards are distributed correctly
-        let final_b_delegator1 = Balances::free_balance(&66);
-        let final_b_validator = Balances::free_balance(&55);
+// ------ admin tests -------
 
-        assert!(final_b_delegator1 > initial_b_delegator1); // Delegator 1 should receive rewards
-        assert!(final_b_validator > initial_b_validator); // Validator should receive rewards
+// test the admin origin can slash right away, without the defense window
+#[test]
+fn force_slash_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-        run_to_block(250);
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
-        // delegato 3 delegates in the middle of the epoch
-        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(88), 55, 200));
-        let initial_b_delegator3 = Balances::free_balance(&88); // balance after delegation
+        assert_ok!(Dpos::force_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 270);
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(10), amount: 80 }.into());
+        System::assert_last_event(Event::ForceSlashed { validator: 55, fraction: Perbill::from_percent(10), amount: 80 }.into());
+
+        assert_noop!(
+            Dpos::force_slash(RuntimeOrigin::root(), 99, Perbill::from_percent(10)),
+            Error::<Test>::ValidatorNotFound
+        );
+    });
+}
 
-        run_to_block(290); // go to just before end of epoch
+// test the admin origin can unregister a validator, its delegations are unbonded like on a normal unregistration
+#[test]
+fn force_unregister_validator_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-        let final_b_delegator3 = Balances::free_balance(&88);
-        assert_eq!(final_b_delegator3, initial_b_delegator3);  // still in current epoch, Delegator 2 should not receive rewards until the next epoch
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
+        assert_ok!(Dpos::force_unregister_validator(RuntimeOrigin::root(), 55));
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert!(Delegators::<Test>::get(66, 55).is_none());
+        assert_eq!(Unlocking::<Test>::get(55)[0].amount, 500);
+        assert_eq!(Unlocking::<Test>::get(66)[0].amount, 300);
+        System::assert_has_event(Event::ValidatorDeregistered { validator: 55 }.into());
+        System::assert_last_event(Event::ValidatorForceUnregistered { validator: 55 }.into());
 
-        run_to_block(300); // go to next epoch
+        assert_noop!(
+            Dpos::force_unregister_validator(RuntimeOrigin::root(), 55),
+            Error::<Test>::ValidatorNotFound
+        );
+    });
+}
 
-        let final_b_delegator2_epoch_2 = Balances::free_balance(&77);
-        assert!(final_b_delegator2_epoch_2 > initial_b_delegator2); // now he should receive rewards
+// test the admin origin can undelegate on behalf of a delegator
+#[test]
+fn force_undelegate_works() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
 
-        run_to_block(400); 
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
-        let final_b_delegator3_epoch_3 = Balances::free_balance(&88);
-        assert!(final_b_delegator3_epoch_3 > initial_b_delegator3); // now he should receive rewards
+        assert_ok!(Dpos::force_undelegate(RuntimeOrigin::root(), 66, 55, 100));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 200);
+        assert_eq!(ValidatorStakes::<Test>::get(55), 700);
+        assert_eq!(Unlocking::<Test>::get(66)[0].amount, 100);
+        System::assert_has_event(Event::Undelegated { delegator: 66, validator: 55, amount: 100 }.into());
+        System::assert_last_event(Event::ForceUndelegated { delegator: 66, validator: 55, amount: 100 }.into());
 
+        // same checks as a normal undelegation
+        assert_noop!(
+            Dpos::force_undelegate(RuntimeOrigin::root(), 66, 55, 195),
+            Error::<Test>::DelegationRemainderTooLow
+        );
+        assert_noop!(
+            Dpos::force_undelegate(RuntimeOrigin::root(), 77, 55, 100),
+            Error::<Test>::NoDelegationFound
+        );
     });
 }
 
-// ----- slash tests -------
+// test the admin calls reject any other origin
 #[test]
-fn slash_validator_successfully() {
+fn admin_calls_reject_other_origins() {
     new_test_ext().execute_with(|| {
-        let _ = env_logger::builder().is_test(true).try_init();
         run_to_block(1);
 
-        // Fund an account with sufficient balance
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
-        // Register a validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        for origin in [RuntimeOrigin::signed(55), RuntimeOrigin::signed(66), RuntimeOrigin::none()] {
+            assert_noop!(
+                Dpos::force_slash(origin.clone(), 55, Perbill::from_percent(10)),
+                sp_runtime::DispatchError::BadOrigin
+            );
+            assert_noop!(
+                Dpos::force_unregister_validator(origin.clone(), 55),
+                sp_runtime::DispatchError::BadOrigin
+            );
+            assert_noop!(
+                Dpos::force_undelegate(origin, 66, 55, 100),
+                sp_runtime::DispatchError::BadOrigin
+            );
+        }
+    });
+}
+
+// ------ deferred slash tests -------
+
+// test a deferred slash waits for the defense window and is applied at the epoch boundary
+#[test]
+fn deferred_slash_applied_after_window() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
-        // Slash the validator
-        assert_ok!(Dpos::test_slash_validator(RuntimeOrigin::root(), 55));
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        System::assert_last_event(Event::SlashDeferred { validator: 55, fraction: Perbill::from_percent(10), apply_epoch: 2 }.into());
+        assert_eq!(UnappliedSlashes::<Test>::get(2, 55).map(|slash| slash.fraction), Some(Perbill::from_percent(10)));
 
-        // Verify storage updates
-        assert!(!PotentialValidators::<Test>::contains_key(&55));
-        assert_eq!(ValidatorStakes::<Test>::get(&55), Zero::zero());
+        // nothing is burned during the defense window
+        run_to_block(199);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(500));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 300);
 
-        // Verify event emission
-        System::assert_last_event(Event::ValidatorSlashed { validator: 55, amount: 800 }.into());
+        run_to_block(200);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 270);
+        assert!(UnappliedSlashes::<Test>::get(2, 55).is_none());
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(10), amount: 80 }.into());
     });
 }
 
-// test for slashing a non registered validator
+// test a second report for the same validator and epoch keeps the higher fraction
 #[test]
-fn slash_non_registered_validator() {
+fn deferred_slash_keeps_highest_fraction() {
     new_test_ext().execute_with(|| {
-        let _ = env_logger::builder().is_test(true).try_init();
         run_to_block(1);
 
-        // Attempt to slash a non-registered validator
-        assert_noop!(
-            Dpos::test_slash_validator(RuntimeOrigin::root(), 55),
-            Error::<Test>::ValidatorNotFound
-        );
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(20)));
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        assert_eq!(UnappliedSlashes::<Test>::get(2, 55).map(|slash| slash.fraction), Some(Perbill::from_percent(20)));
+
+        run_to_block(200);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(400));
     });
 }
 
-// test for slashing a validator with zero stake
+// test governance can cancel a slash during the defense window
 #[test]
-fn slash_validator_with_zero_stake() {
+fn cancel_deferred_slash_works() {
     new_test_ext().execute_with(|| {
-        let _ = env_logger::builder().is_test(true).try_init();
         run_to_block(1);
 
-        // Fund an account with sufficient balance
         Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
 
-        // Register and then unregister a validator
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
-        assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
-
-        // Attempt to slash a validator with zero stake
+        // only the cancel origin can cancel
         assert_noop!(
-            Dpos::test_slash_validator(RuntimeOrigin::root(), 55),
-            Error::<Test>::ValidatorNotFound
+            Dpos::cancel_deferred_slash(RuntimeOrigin::signed(55), 2, 55),
+            sp_runtime::DispatchError::BadOrigin
+        );
+        assert_noop!(
+            Dpos::cancel_deferred_slash(RuntimeOrigin::root(), 3, 55),
+            Error::<Test>::NoDeferredSlash
         );
+
+        assert_ok!(Dpos::cancel_deferred_slash(RuntimeOrigin::root(), 2, 55));
+        System::assert_last_event(Event::DeferredSlashCancelled { validator: 55, epoch: 2 }.into());
+
+        run_to_block(300);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(500));
+        assert_eq!(Balances::total_balance(&55), 1_000);
     });
 }
 
-// Ensure slashing attempts fail for non-validator accounts
+// test stake under review can not be withdrawn or moved away until the slash is applied
 #[test]
-fn slash_non_validator() {
+fn deferred_slash_keeps_funds_held() {
     new_test_ext().execute_with(|| {
-        let _ = env_logger::builder().is_test(true).try_init();
         run_to_block(1);
 
-        // Fund an account with sufficient balance
         Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 600));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 100));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 300)); // unlocks at epoch 2
+
+        // the slash lands in epoch 1, where 66 still backs 55 with 300, and is applied at epoch 3
+        run_to_block(150);
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(50)));
 
-        // Attempt to slash a non-validator account
         assert_noop!(
-            Dpos::test_slash_validator(RuntimeOrigin::root(), 55),
-            Error::<Test>::ValidatorNotFound
+            Dpos::redelegate(RuntimeOrigin::signed(77), 55, 1, 100),
+            Error::<Test>::SlashPending
+        );
+
+        // the chunk matured, but the slash is still pending
+        run_to_block(200);
+        assert_noop!(
+            Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)),
+            Error::<Test>::NothingToWithdraw
         );
+
+        // once applied, the chunk can be withdrawn. the slash took half of the 300 exposed from the bonded delegation
+        run_to_block(300);
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 150);
+        assert_ok!(Dpos::withdraw_unbonded(RuntimeOrigin::signed(66)));
+        assert_eq!(Balances::free_balance(66), 700);
     });
 }
 
@@ -823,20 +3079,26 @@ fn multiple_epoch_transitions() {
         Balances::make_free_balance_be(&66, 1_000);
         Balances::make_free_balance_be(&77, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
-        let initial_b_validator = Balances::free_balance(&55);
-        let initial_b_delegator = Balances::free_balance(&66);
+        let initial_b_validator = Balances::free_balance(55);
+        let initial_b_delegator = Balances::free_balance(66);
 
         // Simulate multiple epoch transitions
         for _ in 0..3 {
             run_to_block(System::block_number() + 100);
         }
 
+        // claim the rewards of the epochs the validator was elected in
+        for epoch in 1..=2 {
+            assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(55), epoch, 55));
+            assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(66), epoch, 55));
+        }
+
         // Verify rewards are distributed correctly
-        let final_b_validator = Balances::free_balance(&55);
-        let final_b_delegator = Balances::free_balance(&66);
+        let final_b_validator = Balances::free_balance(55);
+        let final_b_delegator = Balances::free_balance(66);
 
         assert!(final_b_delegator > initial_b_delegator); // Delegator should receive rewards
         assert!(final_b_validator > initial_b_validator); // Validator should receive rewards
@@ -856,17 +3118,17 @@ fn remove_delegator_mid_epoch() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         run_to_block(50); // Mid-epoch
 
         // Remove the delegator
-        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 300));
+        assert_ok!(Dpos::undelegate(RuntimeOrigin::signed(66), 55, 300));
 
         // Verify storage updates
-        assert!(!Delegators::<Test>::contains_key(&66));
-        let validator_stake = ValidatorStakes::<Test>::get(&55);
+        assert!(!Delegators::<Test>::contains_key(66, 55));
+        let validator_stake = ValidatorStakes::<Test>::get(55);
         assert_eq!(validator_stake, 500);
 
         // Verify event emission
@@ -879,9 +3141,10 @@ fn remove_delegator_mid_epoch() {
 
         run_to_block(100); // Complete epoch
 
-        // Verify no rewards for the removed delegator
-        let final_b_delegator = Balances::free_balance(&66);
-        assert_eq!(final_b_delegator, 1_000); // No change from initial balance
+        // Verify no rewards for the removed delegator, the undelegated stake is still unbonding
+        let final_b_delegator = Balances::free_balance(66);
+        assert_eq!(final_b_delegator, 700);
+        assert_eq!(Balances::total_balance(&66), 1_000); // No change from initial balance
     });
 }
 
@@ -894,7 +3157,7 @@ fn remove_validator_mid_epoch() {
         Balances::make_free_balance_be(&55, 1_000);
         Balances::make_free_balance_be(&66, 1_000);
 
-        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500));
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
         assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
 
         run_to_block(50); // Mid-epoch
@@ -903,9 +3166,9 @@ fn remove_validator_mid_epoch() {
         assert_ok!(Dpos::unregister_validator(RuntimeOrigin::signed(55)));
 
         // Verify storage updates
-        assert!(!PotentialValidators::<Test>::contains_key(&55));
-        assert!(!ValidatorStakes::<Test>::contains_key(&55));
-        assert!(!Delegators::<Test>::contains_key(&66));
+        assert!(!PotentialValidators::<Test>::contains_key(55));
+        assert!(!ValidatorStakes::<Test>::contains_key(55));
+        assert!(!Delegators::<Test>::contains_key(66, 55));
 
         // Verify event emission
         System::assert_last_event(Event::ValidatorDeregistered {
@@ -916,13 +3179,360 @@ fn remove_validator_mid_epoch() {
         run_to_block(100); // Complete epoch
 
         // Verify no rewards for the removed validator and delegator
-        let final_b_validator = Balances::free_balance(&55);
-        let final_b_delegator = Balances::free_balance(&66);
+        let final_b_validator = Balances::free_balance(55);
+        let final_b_delegator = Balances::free_balance(66);
+
+        // stake is still unbonding, so it is held but the total is unchanged
+        assert_eq!(final_b_validator, 500);
+        assert_eq!(final_b_delegator, 700);
+        assert_eq!(Balances::total_balance(&55), 1_000); // No change from initial balance
+        assert_eq!(Balances::total_balance(&66), 1_000); // No change from initial balance
+    });
+}
+
+
+
+
+// ------ downtime tests -------
+
+// test a validator missing its slots for one epoch only gets a warning
+#[test]
+fn offline_validator_warned() {
+    new_test_ext().execute_with(|| {
+        mock::OfflineThreshold::set(Perbill::from_percent(50));
+        run_to_block_offline(100, &[3]);
+
+        System::assert_has_event(Event::OfflineWarning { validator: 3, blocks: 0, expected: 10 }.into());
+        assert_eq!(OfflineStrikes::<Test>::get(3), 1);
+        assert!(CurrentValidators::<Test>::get().contains(&3));
+        assert_eq!(PotentialValidators::<Test>::get(3), Some(100));
+
+        // the validators that authored their share are not penalized
+        assert_eq!(OfflineStrikes::<Test>::get(4), 0);
+    });
+}
+
+// test the penalty grows with every consecutive offline epoch: warning, slash, removal
+#[test]
+fn offline_penalties_escalate() {
+    new_test_ext().execute_with(|| {
+        mock::OfflineThreshold::set(Perbill::from_percent(50));
+        run_to_block(1);
+
+        // a validator with a held self-stake, elected at the first epoch boundary
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        run_to_block_offline(100, &[55]);
+        assert!(CurrentValidators::<Test>::get().contains(&55));
+
+        run_to_block_offline(200, &[55]);
+        System::assert_has_event(Event::OfflineWarning { validator: 55, blocks: 0, expected: 10 }.into());
+
+        // second epoch offline, the slash goes through the defense window
+        run_to_block_offline(300, &[55]);
+        System::assert_has_event(Event::OfflineSlashed { validator: 55, fraction: Perbill::from_percent(10) }.into());
+        assert_eq!(UnappliedSlashes::<Test>::get(5, 55).map(|slash| slash.fraction), Some(Perbill::from_percent(10)));
+        assert!(CurrentValidators::<Test>::get().contains(&55));
+
+        // third epoch offline, the validator is taken out of the new set
+        run_to_block_offline(400, &[55]);
+        System::assert_has_event(Event::OfflineRemoved { validator: 55 }.into());
+        assert!(!CurrentValidators::<Test>::get().contains(&55));
+        assert_eq!(CurrentValidators::<Test>::get().len(), 9);
+
+        // the deferred slash lands after the window
+        run_to_block_offline(500, &[55]);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+    });
+}
+
+// test an epoch online clears the strikes
+#[test]
+fn offline_strikes_reset_when_online() {
+    new_test_ext().execute_with(|| {
+        mock::OfflineThreshold::set(Perbill::from_percent(50));
+        run_to_block_offline(100, &[3]);
+        assert_eq!(OfflineStrikes::<Test>::get(3), 1);
+
+        run_to_block_offline(200, &[]);
+        assert_eq!(OfflineStrikes::<Test>::get(3), 0);
+
+        // the next offline epoch starts over with a warning
+        run_to_block_offline(300, &[3]);
+        assert_eq!(OfflineStrikes::<Test>::get(3), 1);
+        assert!(UnappliedSlashes::<Test>::iter().next().is_none());
+    });
+}
+
+// ------ offence tests -------
+
+// test an equivocation report disables the offender and defers its slash with the reporters
+#[test]
+fn offence_disables_and_defers_slash() {
+    use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        let offenders = vec![OffenceDetails { offender: (55, 800), reporters: vec![77, 88] }];
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::from_percent(10)],
+            0,
+        );
+
+        System::assert_has_event(Event::ValidatorDisabled { validator: 55 }.into());
+        assert!(DisabledValidators::<Test>::get().contains(&55));
+        let slash = UnappliedSlashes::<Test>::get(2, 55).unwrap();
+        assert_eq!(slash.fraction, Perbill::from_percent(10));
+        assert_eq!(slash.reporters.into_inner(), vec![77, 88]);
+
+        // disabled for the rest of the epoch only
+        run_to_block(100);
+        assert!(DisabledValidators::<Test>::get().is_empty());
+
+        // the reporters share 10% of the 80 slashed
+        run_to_block(200);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        System::assert_has_event(Event::ReporterRewarded { reporter: 77, amount: 4 }.into());
+        System::assert_has_event(Event::ReporterRewarded { reporter: 88, amount: 4 }.into());
+        assert_eq!(Balances::free_balance(77), 4);
+        assert_eq!(Balances::free_balance(88), 4);
+
+        // the rest of the slashed funds goes to the `Slash` handler
+        assert_eq!(Balances::free_balance(SlashTreasury::get()), 72);
+    });
+}
+
+// test stake that joins during the defense window is not slashed for the offence under review
+#[test]
+fn deferred_slash_spares_stake_joining_later() {
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        // 55 is active in epoch 1, the exposure is the snapshot of the epoch
+        run_to_block(150);
+        assert_ok!(Dpos::test_defer_slash(RuntimeOrigin::root(), 55, Perbill::from_percent(10)));
+        assert_eq!(SlashExposures::<Test>::get((3, 55, 55)), 500);
+        assert_eq!(SlashExposures::<Test>::get((3, 55, 66)), 300);
+
+        // 77 delegates while the slash is pending
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 400));
+
+        run_to_block(300);
+        assert_eq!(PotentialValidators::<Test>::get(55), Some(450));
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 270);
+        assert_eq!(Delegators::<Test>::get(77, 55).unwrap().amount, 400);
+        assert_eq!(ValidatorStakes::<Test>::get(55), 450 + 270 + 400);
+        System::assert_has_event(Event::ValidatorSlashed { validator: 55, fraction: Perbill::from_percent(10), amount: 80 }.into());
+        assert!(SlashExposures::<Test>::iter().next().is_none());
+    });
+}
+
+// test an offence reported for an earlier session slashes the stake of that session's epoch, and is ignored once the
+// snapshot of it is pruned
+#[test]
+fn offence_slashes_stake_of_its_session() {
+    use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        Balances::make_free_balance_be(&77, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+
+        // 77 only delegates in epoch 2, the offence happened in session 1 and is reported in epoch 3
+        run_to_block(250);
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(77), 55, 400));
+        run_to_block(350);
+        let offenders = vec![OffenceDetails { offender: (55, 800), reporters: vec![] }];
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::from_percent(10)],
+            1,
+        );
+        assert_eq!(SlashExposures::<Test>::get((5, 55, 66)), 300);
+        assert!(!SlashExposures::<Test>::contains_key((5, 55, 77)));
+
+        run_to_block(500);
+        assert_eq!(Delegators::<Test>::get(66, 55).unwrap().amount, 270);
+        assert_eq!(Delegators::<Test>::get(77, 55).unwrap().amount, 400);
+
+        // the snapshot of session 1 is gone after `HistoryDepth` epochs, the stake of back then is unknown
+        run_to_block(650);
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::from_percent(10)],
+            1,
+        );
+        assert!(UnappliedSlashes::<Test>::iter().next().is_none());
+    });
+}
+
+// test an offence with a zero fraction only disables the offender
+#[test]
+fn offence_with_zero_fraction_only_disables() {
+    use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        let offenders = vec![OffenceDetails { offender: (3, 100), reporters: vec![] }];
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::zero()],
+            0,
+        );
+
+        assert!(DisabledValidators::<Test>::get().contains(&3));
+        assert!(UnappliedSlashes::<Test>::iter().next().is_none());
+    });
+}
+
+// test a disabled validator is not also penalized for the slots it could not author
+#[test]
+fn disabled_validator_not_offline() {
+    use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+    new_test_ext().execute_with(|| {
+        mock::OfflineThreshold::set(Perbill::from_percent(50));
+        run_to_block(1);
+
+        let offenders = vec![OffenceDetails { offender: (3, 100), reporters: vec![] }];
+        <Dpos as OnOffenceHandler<u64, (u64, u128), frame_support::weights::Weight>>::on_offence(
+            &offenders,
+            &[Perbill::zero()],
+            0,
+        );
+
+        run_to_block_offline(100, &[3]);
+        assert_eq!(OfflineStrikes::<Test>::get(3), 0);
+    });
+}
+
+// ------ session tests -------
+
+// the set elected at an epoch boundary only authors after `pallet_session` activated it a session later, the validators
+// that really authored an epoch are the ones scored and paid for it
+#[test]
+fn session_activates_elected_set_an_epoch_later() {
+    new_session_test_ext().execute_with(|| {
+        OfflineThreshold::set(Perbill::from_percent(50));
+        run_to_block_with_session(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Session::set_keys(RuntimeOrigin::signed(55), UintAuthorityId(55), vec![]));
+
+        // the boundary elects 55 in place of a genesis validator, the session pallet only queues it
+        run_to_block_with_session(150);
+        let elected = CurrentValidators::<Test>::get();
+        let active = ActiveValidators::<Test>::get();
+        let dropped = *active.iter().find(|validator| !elected.contains(validator)).unwrap();
+        assert!(elected.contains(&55));
+        assert_eq!(pallet_session::Validators::<Test>::get(), active.into_inner());
+        assert_eq!(BlockCount::<Test>::get(55), 0);
+        assert!(BlockCount::<Test>::get(dropped) > 0);
+
+        // the dropped validator authored epoch 1 and is paid for it, 55 had no slots and is not held responsible
+        run_to_block_with_session(250);
+        assert!(EpochRewards::<Test>::contains_key(1, dropped));
+        assert!(!EpochRewards::<Test>::contains_key(1, 55));
+        assert_eq!(OfflineStrikes::<Test>::get(55), 0);
+        assert_eq!(Reputation::<Test>::get(55).tenure, 0);
+        assert_eq!(Reputation::<Test>::get(dropped).tenure, 2);
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(dropped), 1, dropped));
+
+        // 55 authors epoch 2 and is paid for it, the dropped validator is gone
+        assert_eq!(pallet_session::Validators::<Test>::get(), elected.into_inner());
+        assert!(BlockCount::<Test>::get(55) > 0);
+        assert_eq!(BlockCount::<Test>::get(dropped), 0);
+        run_to_block_with_session(301);
+        assert!(EpochRewards::<Test>::contains_key(2, 55));
+        assert!(!EpochRewards::<Test>::contains_key(2, dropped));
+        assert_ok!(Dpos::claim_rewards(RuntimeOrigin::signed(55), 2, 55));
+    });
+}
+
+// test the historical session manager reports the stake of every validator
+#[test]
+fn historical_session_manager_reports_stake() {
+    use pallet_session::historical::SessionManager;
+
+    new_test_ext().execute_with(|| {
+        run_to_block(1);
+
+        Balances::make_free_balance_be(&55, 1_000);
+        Balances::make_free_balance_be(&66, 1_000);
+        assert_ok!(Dpos::register_validator(RuntimeOrigin::signed(55), 500, Perbill::from_percent(30)));
+        assert_ok!(Dpos::delegate(RuntimeOrigin::signed(66), 55, 300));
+        run_to_block(100);
+
+        let validators = <Dpos as SessionManager<u64, u128>>::new_session(2).unwrap();
+        assert!(validators.contains(&(55, 800)));
+
+        // the full identification of a validator is its current total stake
+        assert_eq!(crate::session::StakeOf::<Test>::convert(55), Some(800));
+        assert_eq!(crate::session::StakeOf::<Test>::convert(99), None);
+    });
+}
 
-        assert_eq!(final_b_validator, 1_000); // No change from initial balance
-        assert_eq!(final_b_delegator, 1_000); // No change from initial balance
+// test an empty validator set is never handed to the session pallet
+#[test]
+fn session_manager_keeps_set_when_empty() {
+    use pallet_session::SessionManager;
+
+    new_test_ext().execute_with(|| {
+        CurrentValidators::<Test>::kill();
+        assert_eq!(<Dpos as SessionManager<u64>>::new_session(1), None);
+    });
+}
+
+// ------ authorship tests -------
+
+// test the block author is read from the pre-runtime digest of the block, like Aura's slot digest
+#[test]
+fn author_found_from_pre_runtime_digest() {
+    use codec::Encode;
+    use sp_runtime::DigestItem;
+
+    new_test_ext().execute_with(|| {
+        run_to_block(2);
+        mock::Author::set(1);
+        let before = BlockCount::<Test>::get(7);
+
+        // the digest wins over the fallback author
+        System::deposit_log(DigestItem::PreRuntime(TEST_ENGINE_ID, 7u64.encode()));
+        assert_eq!(Dpos::find_author(), Some(7));
+
+        Dpos::on_finalize(System::block_number());
+        assert_eq!(BlockCount::<Test>::get(7), before + 1);
     });
 }
 
+// test other engines' digests are ignored
+#[test]
+fn author_ignores_other_engine_digests() {
+    use codec::Encode;
+    use sp_runtime::DigestItem;
 
+    new_test_ext().execute_with(|| {
+        run_to_block(2);
+        mock::Author::set(3);
 
+        System::deposit_log(DigestItem::PreRuntime(*b"aura", 7u64.encode()));
+        assert_eq!(Dpos::find_author(), Some(3));
+    });
+}
@@ -18,8 +18,14 @@ use frame::{
 		},
 		prelude::*,
 	},
-	traits::{FindAuthor, One},
+	traits::One,
 };
+use frame_election_provider_support::{
+	bounds::{ElectionBounds, ElectionBoundsBuilder},
+	onchain, SequentialPhragmen,
+};
+use sp_consensus_aura::sr25519::AuthorityId as AuraId;
+use sp_runtime::traits::{ConvertInto, OpaqueKeys};
 use pallet_transaction_payment::{ConstFeeMultiplier, FeeDetails, Multiplier, RuntimeDispatchInfo};
 
 #[runtime_version]
@@ -75,6 +81,23 @@ mod runtime {
 	#[runtime::pallet_index(7)]
 	pub type Dpos = pallet_dpos;
 
+	/// Rotates the validators elected by dpos, one session after they were elected. It must come after `Dpos`, so the
+	/// ended epoch is accounted against the validators that authored it before the queued set is activated.
+	#[runtime::pallet_index(8)]
+	pub type Session = pallet_session;
+
+	/// Keeps the validator set and stake of past sessions, used to check reported offences.
+	#[runtime::pallet_index(9)]
+	pub type Historical = pallet_session::historical;
+
+	/// Block production, the authorities are the aura keys of the current session's validators.
+	#[runtime::pallet_index(10)]
+	pub type Aura = pallet_aura;
+
+	/// Tracks the author of the current block.
+	#[runtime::pallet_index(11)]
+	pub type Authorship = pallet_authorship;
+
 	#[runtime::pallet_index(99)]
 	pub type Timestamp = pallet_timestamp;
 }
@@ -120,7 +143,8 @@ parameter_types! {
 
 #[derive_impl(pallet_transaction_payment::config_preludes::TestDefaultConfig)]
 impl pallet_transaction_payment::Config for Runtime {
-	type OnChargeTransaction = pallet_transaction_payment::FungibleAdapter<Balances, ()>;
+	// Part of the fees goes to the block author's reward pool, the rest is burned.
+	type OnChargeTransaction = pallet_transaction_payment::FungibleAdapter<Balances, pallet_dpos::FeeSink<Runtime>>;
 	type OperationalFeeMultiplier = ConstU8<5>;
 	type FeeMultiplierUpdate = ConstFeeMultiplier<FeeMultiplier>;
 	// These two define what the transaction fee would.
@@ -128,9 +152,15 @@ impl pallet_transaction_payment::Config for Runtime {
 	type LengthToFee = FixedFee<0, Balance>;
 }
 
-// we don't really need this pallet, but it PJS apps requires :(
+/// Blocks are produced every 6 seconds.
+pub const SLOT_DURATION: u64 = 6000;
+
+// the timestamp drives the Aura slots
 #[derive_impl(pallet_timestamp::config_preludes::TestDefaultConfig)]
-impl pallet_timestamp::Config for Runtime {}
+impl pallet_timestamp::Config for Runtime {
+	type OnTimestampSet = Aura;
+	type MinimumPeriod = ConstU64<{ SLOT_DURATION / 2 }>;
+}
 
 parameter_types! {
 	pub const AssetDeposit: Balance = 100;
@@ -143,48 +173,151 @@ parameter_types! {
 
 parameter_types! {
 	pub const MaxValidators: u32 = 10;
+	pub const MaxCommission: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(50);
+	pub const OfflineThreshold: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(50);
+	pub const OfflineSlashFraction: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(5);
+	pub const ReporterReward: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(10);
+	pub const DposPalletId: frame::deps::frame_support::PalletId = frame::deps::frame_support::PalletId(*b"dpos/pot");
+	pub const RewardFunding: pallet_dpos::RewardSource = pallet_dpos::RewardSource::Pot;
+	pub const InflationMin: sp_runtime::Perbill = sp_runtime::Perbill::from_parts(25_000_000);
+	pub const InflationMax: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(10);
+	pub const IdealStakingRatio: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(50);
+	pub const InflationFalloff: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(25);
+	pub const CurveBoost: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(20);
+	pub const CurveCap: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(20);
+	pub const ReputationWeight: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(20);
+	pub const FeeShare: sp_runtime::Perbill = sp_runtime::Perbill::from_percent(80);
 }
 
-pub struct BlockAuthor;
-impl FindAuthor<AccountId> for BlockAuthor {
-	fn find_author<'a, I>(_: I) -> Option<AccountId>
-	where
-		I: 'a + IntoIterator<Item = ([u8; 4], &'a [u8])>,
-	{
-		// return a random-ish block author previously reported.
-		let last_known_set = ValidatorSet::get();
-		if last_known_set.is_empty() {
-			return None;
-		}
+/// Validators disabled by an offence are disabled in the session pallet, so Aura skips their slots.
+pub struct DisableInSession;
+impl pallet_dpos::DisableValidator<AccountId> for DisableInSession {
+	fn disable_validator(who: &AccountId) {
+		Session::disable(who);
+	}
+}
+
+/// Block production with Aura, the session pallet sets the elected validators' aura keys as authorities.
+impl pallet_aura::Config for Runtime {
+	type AuthorityId = AuraId;
+	type MaxAuthorities = MaxValidators;
+	type DisabledValidators = Session;
+	type AllowMultipleBlocksPerSlot = ConstBool<false>;
+	type SlotDuration = ConstU64<SLOT_DURATION>;
+}
 
-		let index = frame_system::Pallet::<Runtime>::block_number() as usize % last_known_set.len();
-		Some(last_known_set[index].clone())
+/// The block author is found through the Aura pre-runtime digest and the session key-ownership mapping.
+impl pallet_authorship::Config for Runtime {
+	type FindAuthor = pallet_session::FindAccountFromAuthorIndex<Self, Aura>;
+	type EventHandler = ();
+}
+
+sp_runtime::impl_opaque_keys! {
+	pub struct SessionKeys {
+		pub aura: Aura,
 	}
 }
 
 parameter_types! {
-	// This is a temporary storage that will keep the validators. In reality, this would have been
-	// `pallet-aura` or another pallet that would consume these.
-	pub storage ValidatorSet: Vec<AccountId> = vec![];
+	// One session is one dpos epoch.
+	pub const EpochDuration: u32 = 100;
 }
 
-pub struct StoreNewValidatorSet;
-impl pallet_dpos::ReportNewValidatorSet<AccountId> for StoreNewValidatorSet {
-	fn report_new_validator_set(new_set: Vec<AccountId>) {
-		ValidatorSet::set(&new_set);
-	}
+impl pallet_session::Config for Runtime {
+	type RuntimeEvent = RuntimeEvent;
+	type ValidatorId = AccountId;
+	type ValidatorIdOf = ConvertInto;
+	type ShouldEndSession = pallet_session::PeriodicSessions<EpochDuration, ConstU32<0>>;
+	type NextSessionRotation = pallet_session::PeriodicSessions<EpochDuration, ConstU32<0>>;
+	type SessionManager = pallet_session::historical::NoteHistoricalRoot<Runtime, Dpos>;
+	type SessionHandler = <SessionKeys as OpaqueKeys>::KeyTypeIdProviders;
+	type Keys = SessionKeys;
+	type WeightInfo = ();
 }
 
+impl pallet_session::historical::Config for Runtime {
+	// The total stake of the validator when the session started.
+	type FullIdentification = Balance;
+	type FullIdentificationOf = pallet_dpos::session::StakeOf<Runtime>;
+}
+
+parameter_types! {
+	pub ElectionBoundsOnChain: ElectionBounds = ElectionBoundsBuilder::default().build();
+}
+
+/// Elect the dpos validator set on chain with sequential Phragmén, the pallet provides the voters and targets.
+pub struct OnChainSeqPhragmen;
+impl onchain::Config for OnChainSeqPhragmen {
+	type System = Runtime;
+	type Solver = SequentialPhragmen<AccountId, sp_runtime::Perbill>;
+	type DataProvider = Dpos;
+	type WeightInfo = ();
+	type MaxWinners = MaxValidators;
+	type Bounds = ElectionBoundsOnChain;
+}
 
 /// Configure the pallet-dpos in pallets/dpos.
 impl pallet_dpos::Config for Runtime {
 	type RuntimeEvent = RuntimeEvent;
 	type NativeBalance = Balances;
 	type MaxValidators = MaxValidators;
-	type FindAuthor = BlockAuthor;
-	type ReportNewValidatorSet = StoreNewValidatorSet;
-	type EpochDuration = ConstU32<100>;
+	// Rewards are accounted to the validator that authored the block according to Aura.
+	type FindAuthor = pallet_session::FindAccountFromAuthorIndex<Self, Aura>;
+	// The validators are read by `pallet_session` through the session manager, which activates them a session later.
+	type ReportNewValidatorSet = ();
+	type SessionRotation = ConstBool<true>;
+	// Sequential Phragmén on chain, every account votes for all the validators it backs.
+	type ElectionProvider = onchain::OnChainExecution<OnChainSeqPhragmen>;
+	type EpochDuration = EpochDuration;
 	type RuntimeHoldReason = RuntimeHoldReason;
+	// Unbonded stake stays slashable for 7 epochs (~70 minutes) before it can be withdrawn.
+	type UnbondingEpochs = ConstU32<7>;
+	type MaxUnlockingChunks = ConstU32<32>;
+	type MaxDelegationsPerDelegator = ConstU32<16>;
+	// Defaults for the minimum amounts, root can change them with `set_minimums`.
+	type MinValidatorBond = ConstU128<1_000>;
+	type MinDelegation = ConstU128<100>;
+	type AdminOrigin = frame_system::EnsureRoot<AccountId>;
+	// A delegator can move stake to another validator once per epoch.
+	type RedelegationCooldown = ConstU32<1>;
+	// Rewards can be claimed for 84 epochs (~14 hours) after they were recorded.
+	type HistoryDepth = ConstU32<84>;
+	// Rewards are paid out automatically, 64 delegators per block, and can still be claimed earlier.
+	type AutomaticPayouts = ConstBool<true>;
+	type MaxDelegatorsPerPage = ConstU32<64>;
+	type MaxCommission = MaxCommission;
+	// Commission increases wait as long as unbonding, so delegators can leave before they apply.
+	type CommissionChangeDelay = ConstU32<7>;
+	// Slashes wait 6 epochs (~60 minutes) for an appeal, less than unbonding so the stake is still there.
+	type SlashDeferEpochs = ConstU32<6>;
+	type SlashCancelOrigin = frame_system::EnsureRoot<AccountId>;
+	// A validator authoring less than half of its share of the epoch is offline, the second time in a row costs 5%.
+	type OfflineThreshold = OfflineThreshold;
+	type OfflineSlashFraction = OfflineSlashFraction;
+	// Offence reporters share 10% of the slashed amount.
+	type ReporterReward = ReporterReward;
+	type MaxReporters = ConstU32<16>;
+	type DisableValidator = DisableInSession;
+	// There is no treasury in this runtime, slashed funds refill the reward pot.
+	type Slash = pallet_dpos::RewardPotInflow<Runtime>;
+	type RewardRemainder = ();
+	// Rewards are paid from the reward pot funded at genesis instead of minted, so issuance stays capped.
+	type PalletId = DposPalletId;
+	type RewardFunding = RewardFunding;
+	// Between 2.5% and 10% a year, highest with half of the issuance staked.
+	type InflationMin = InflationMin;
+	type InflationMax = InflationMax;
+	type IdealStakingRatio = IdealStakingRatio;
+	type InflationFalloff = InflationFalloff;
+	// 100 blocks of 6 seconds per epoch.
+	type EpochsPerYear = ConstU32<52_596>;
+	// Validators below the median stake earn up to 20% more per block, above it the reward follows the stake and above 20% of the stake every extra token earns progressively less.
+	type RewardCurve = pallet_dpos::reward_curve::DecentralizationCurve<CurveBoost, CurveCap>;
+	// Tenure counts fully after a day in the validator set, reputation decides a fifth of the reward weight.
+	type ReputationTenure = ConstU32<144>;
+	type ReputationWeight = ReputationWeight;
+	// 80% of the fees of a block go to its author's reward pool, shared with the delegators like the block rewards.
+	type FeeShare = FeeShare;
 }
 
 
@@ -291,14 +424,24 @@ impl_runtime_apis! {
 	}
 
 	impl apis::SessionKeys<Block> for Runtime {
-		fn generate_session_keys(_seed: Option<Vec<u8>>) -> Vec<u8> {
-			Default::default()
+		fn generate_session_keys(seed: Option<Vec<u8>>) -> Vec<u8> {
+			SessionKeys::generate(seed)
 		}
 
 		fn decode_session_keys(
-			_encoded: Vec<u8>,
+			encoded: Vec<u8>,
 		) -> Option<Vec<(Vec<u8>, apis::KeyTypeId)>> {
-			Default::default()
+			SessionKeys::decode_into_raw_public_keys(&encoded)
+		}
+	}
+
+	impl sp_consensus_aura::AuraApi<Block, AuraId> for Runtime {
+		fn slot_duration() -> sp_consensus_aura::SlotDuration {
+			sp_consensus_aura::SlotDuration::from_millis(Aura::slot_duration())
+		}
+
+		fn authorities() -> Vec<AuraId> {
+			pallet_aura::Authorities::<Runtime>::get().into_inner()
 		}
 	}
 
@@ -308,6 +451,21 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_dpos_runtime_api::DposApi<Block, AccountId> for Runtime {
+		fn current_apr() -> sp_runtime::Perbill {
+			Dpos::current_apr()
+		}
+		fn current_inflation() -> sp_runtime::Perbill {
+			Dpos::current_inflation()
+		}
+		fn staking_ratio() -> sp_runtime::Perbill {
+			Dpos::staking_ratio()
+		}
+		fn reputation(validator: AccountId) -> sp_runtime::Perbill {
+			Dpos::reputation_score(&validator)
+		}
+	}
+
 	impl pallet_transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,